@@ -0,0 +1,340 @@
+//! Module for letting trains drive to a destination on their own.
+//!
+//! In driving mode, clicking on a track while a train is selected sends the train there.
+
+use std::ops::Add;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::SimCommand,
+    input::{DriveAction, DriveInput, MenuState},
+    interact::TileClickEvent,
+    railroad::{RailGraph, Track},
+    tilemap::Joint,
+    trains::*,
+};
+
+/// How close in tracks the front of a train has to be to the destination to count as arrived.
+const ARRIVAL_TOLERANCE: f32 = 0.01;
+/// The fraction of the full brake force the autopilot plans with, the rest is a reserve.
+const PLANNED_BRAKING: f32 = 0.9;
+/// How much slower than the speed limits in m/s the autopilot drives.
+const SPEED_LIMIT_MARGIN: f32 = 1.0;
+
+pub struct AutopilotPlugin;
+
+impl Plugin for AutopilotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, drive_autopilot.in_set(ControlSet::Drive));
+    }
+}
+
+/// Sends the selected train to the clicked track.
+pub struct AutopilotUiPlugin;
+
+impl Plugin for AutopilotUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            set_destination_system.run_if(in_state(MenuState::Driving)),
+        );
+    }
+}
+
+/// Makes a train drive on its own and stop with its front exactly at `destination`.
+///
+/// The route is planned whenever this component is inserted or changed, and again if the path
+/// was cut short, e.g. by demolishing a track on it.
+/// The component is removed once the train has arrived, or if there is no route anymore.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Autopilot {
+    pub destination: Joint,
+}
+
+/// The joint in front of the train, from where new routes for it start.
+pub fn route_start(trail: &Trail) -> Joint {
+    let mut planned = trail.clone();
+    planned.remove_lead();
+    *planned
+        .path
+        .last()
+        .expect("Invariant: trail is never empty")
+}
+
+/// Returns true if the path of the train ends at `destination`, in either direction.
+fn leads_to(trail: &Trail, destination: Joint) -> bool {
+    let end = *trail.path.last().expect("Invariant: trail is never empty");
+    end == destination || end == destination.opposite()
+}
+
+/// Returns true if the front of the train is at `destination`, at the end of its path.
+pub fn has_arrived(trail: &Trail, destination: Joint) -> bool {
+    leads_to(trail, destination) && trail.distance_to_end() <= ARRIVAL_TOLERANCE
+}
+
+/// Replaces the lead of the trail with the shortest route to `destination` (in either
+/// direction) and sets the switches along it.
+///
+/// Returns false if there is no such route, in which case nothing is changed.
+fn plan_route(trail: &mut Trail, rail_graph: &mut RailGraph, destination: Joint) -> bool {
+    let start = route_start(trail);
+    let Some(route) = [destination, destination.opposite()]
+        .into_iter()
+        .filter_map(|goal| rail_graph.find_route(start, goal))
+        .min_by_key(|route| route.len())
+    else {
+        return false;
+    };
+
+    for pair in route.windows(2) {
+        let track = Track::from_joints(pair[0], pair[1]).expect("Routes only contain tracks");
+        if rail_graph.is_switch(track.joint) {
+            rail_graph.set_switch(track.joint, track.heading);
+        }
+    }
+    trail.remove_lead();
+    trail.path.extend_from_slice(&route[1..]);
+    true
+}
+
+/// Returns the speed limits of the tracks ahead of the front of the train up to the end of
+/// its path, with the distance to their start in straight tracks.
+fn speed_limits_ahead(trail: &Trail, rail_graph: &RailGraph) -> Vec<(f32, f32)> {
+    let segment = trail.path_progress.floor() as usize;
+    let mut distance = 0.0;
+    let mut limits = Vec::new();
+    for (index, edge) in trail.path.windows(2).enumerate().skip(segment) {
+        let covered = if index == segment {
+            trail.path_progress.fract()
+        } else {
+            if let Some(limit) = rail_graph.speed_limit(edge[0], edge[1]) {
+                limits.push((distance, limit));
+            }
+            0.0
+        };
+        let length = Track::from_joints(edge[0], edge[1]).map_or(1.0, |track| track.length());
+        distance += (1.0 - covered) * length;
+    }
+    limits
+}
+
+/// System to set throttle and brake of trains with an [`Autopilot`], such that they accelerate
+/// up to their maximum velocity or the speed limit, and brake just in time for slower tracks
+/// ahead and the destination.
+pub(crate) fn drive_autopilot(
+    mut commands: Commands,
+    mut rail_graph: ResMut<RailGraph>,
+    mut trains: Query<
+        (
+            Entity,
+            Ref<Autopilot>,
+            &mut Trail,
+            &mut Velocity,
+            &mut Controller,
+            &Vehicles,
+        ),
+        (With<TrainMarker>, Without<Crashed>),
+    >,
+    vehicles: Query<&VehicleStats>,
+) {
+    for (train, autopilot, mut trail, mut velocity, mut controller, train_vehicles) in
+        trains.iter_mut()
+    {
+        let cut_short = !leads_to(&trail, autopilot.destination);
+        if (autopilot.is_changed() || cut_short)
+            && !plan_route(&mut trail, &mut rail_graph, autopilot.destination)
+        {
+            warn!(
+                "Train {train:?} has no route to {:?}",
+                autopilot.destination.tile
+            );
+            *controller = if cut_short {
+                // Stop in front of the gap instead of running off the end of the path
+                Controller {
+                    throttle: 0.0,
+                    brake: 1.0,
+                }
+            } else {
+                Controller::default()
+            };
+            commands.entity(train).remove::<Autopilot>();
+            continue;
+        }
+
+        if has_arrived(&trail, autopilot.destination) {
+            info!(
+                "Train {train:?} arrived at {:?}",
+                autopilot.destination.tile
+            );
+            velocity.velocity = 0.0;
+            *controller = Controller::default();
+            commands.entity(train).remove::<Autopilot>();
+            continue;
+        }
+
+        let total_stats = train_vehicles
+            .iter()
+            .filter_map(|id| vehicles.get(id).ok())
+            .fold(VehicleStats::additive_identiy(), VehicleStats::add);
+        let max_decceleration = total_stats.braking_force / total_stats.weight;
+        // The decceleration to be at `target` velocity after `distance` tracks
+        let current = velocity.velocity;
+        let decceleration_to = |target: f32, distance: f32| {
+            (current * current - target * target).max(0.0)
+                / (2. * distance.max(f32::EPSILON) * METER_PER_TRACK)
+        };
+        // The path ends at the destination, where the train stops exactly
+        let needed_decceleration = speed_limits_ahead(&trail, &rail_graph)
+            .into_iter()
+            .map(|(distance, limit)| decceleration_to(limit - SPEED_LIMIT_MARGIN, distance))
+            .fold(decceleration_to(0.0, trail.distance_to_end()), f32::max);
+        // The slowest track any vehicle is on
+        let speed_limit = trail
+            .trim()
+            .windows(2)
+            .filter_map(|edge| rail_graph.speed_limit(edge[0], edge[1]))
+            .fold(f32::INFINITY, f32::min);
+
+        if needed_decceleration >= PLANNED_BRAKING * max_decceleration {
+            controller.throttle = 0.0;
+            controller.brake = (needed_decceleration / max_decceleration).min(1.0);
+        } else if current >= speed_limit - SPEED_LIMIT_MARGIN {
+            // Coast along at the speed limit
+            controller.throttle = 0.0;
+            controller.brake = 0.0;
+        } else {
+            controller.throttle = 1.0;
+            controller.brake = 0.0;
+        }
+    }
+}
+
+/// System to send the player controlled train to the clicked joint.
+///
+/// Clicks while [`DriveAction::AppendOrder`] is held edit the train's orders instead.
+fn set_destination_system(
+    mut sim_commands: MessageWriter<SimCommand>,
+    mut click_event: MessageReader<TileClickEvent>,
+    input: Single<&DriveInput>,
+    rail_graph: Res<RailGraph>,
+    trains: Query<&TrainId, With<PlayerControlledTrain>>,
+) {
+    if input.pressed(&DriveAction::AppendOrder) {
+        click_event.clear();
+        return;
+    }
+    for ev in click_event.read() {
+        let Some(side) = ev.side else {
+            // for now ignore clicks in the center: might be ambigous
+            continue;
+        };
+        let destination = Joint {
+            tile: ev.coord,
+            side,
+        };
+        if !rail_graph.graph.contains_node(destination) {
+            continue;
+        }
+
+        for &train in &trains {
+            debug!("Sending train {train:?} to {:?}", destination.tile);
+            sim_commands.write(SimCommand::SetDestination { train, destination });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::headless_app;
+    use crate::railroad::TrackType;
+    use crate::tilemap::{Direction, Tile};
+
+    /// Applies `command` in the next fixed step.
+    fn run(app: &mut App, command: SimCommand) {
+        app.world_mut().write_message(command);
+        app.update();
+    }
+
+    #[test]
+    fn finds_speed_limits_ahead() {
+        let start = Joint {
+            tile: Tile(0, 0),
+            side: Direction::EAST,
+        };
+        let tracks = [
+            (start, TrackType::Straight),
+            (start.next_straight(), TrackType::Straight),
+            (start.next_straight().next_straight(), TrackType::CurvedLeft),
+        ]
+        .map(|(joint, heading)| Track { joint, heading });
+        let mut rail_graph = RailGraph::default();
+        for track in tracks {
+            rail_graph.add_double_track(track);
+        }
+        rail_graph.set_speed_limit(tracks[1], Some(30.0));
+        let trail = Trail {
+            path: vec![
+                start,
+                tracks[1].joint,
+                tracks[2].joint,
+                tracks[2].end_joint(),
+            ],
+            path_progress: 0.25,
+            length: 0,
+        };
+
+        let limits = speed_limits_ahead(&trail, &rail_graph);
+        assert_eq!(limits.len(), 2);
+        assert!((limits[0].0 - 0.75).abs() < 1e-5);
+        assert_eq!(limits[0].1, 30.0);
+        assert!((limits[1].0 - 1.75).abs() < 1e-5);
+        assert_eq!(limits[1].1, TrackType::CurvedLeft.default_speed_limit());
+    }
+    #[test]
+    fn stops_when_the_route_is_demolished() {
+        let tracks: Vec<_> =
+            iter::successors(Some(Joint::default()), |joint| Some(joint.next_straight()))
+                .take(8)
+                .map(|joint| Track {
+                    joint,
+                    heading: TrackType::Straight,
+                })
+                .collect();
+        let mut app = headless_app();
+        app.update();
+        run(&mut app, SimCommand::LayTracks(tracks.clone()));
+        run(
+            &mut app,
+            SimCommand::SpawnTrain {
+                joint: tracks[0].joint,
+                vehicle_type: VehicleType::Locomotive,
+            },
+        );
+        let destination = tracks[6].end_joint();
+        run(
+            &mut app,
+            SimCommand::SetDestination {
+                train: TrainId(1),
+                destination,
+            },
+        );
+        let mut trains = app
+            .world_mut()
+            .query_filtered::<(Has<Autopilot>, &Trail, &Controller), With<TrainMarker>>();
+        let (has_autopilot, trail, _) = trains.single(app.world()).unwrap();
+        assert!(has_autopilot);
+        assert!(leads_to(trail, destination));
+
+        run(&mut app, SimCommand::Demolish(tracks[5].joint));
+        app.update();
+        let (has_autopilot, trail, controller) = trains.single(app.world()).unwrap();
+        assert!(!has_autopilot);
+        assert_eq!(*trail.path.last().unwrap(), tracks[5].joint);
+        assert_eq!(controller.brake, 1.0);
+    }
+}
@@ -0,0 +1,129 @@
+//! Replays a recorded session without a window, see [`hexrails::replay`].
+//!
+//! ```text
+//! hexrails-replay <start savegame> <log> [--expect <end savegame>] [--output <file>]
+//! ```
+//!
+//! Loads the start savegame, applies the commands of the log at their ticks and stops at
+//! the end tick of the log. With `--expect`, the resulting game is compared to the given
+//! savegame, exiting with an error if they differ. Which train is selected is ignored,
+//! since selecting isn't a command. With `--output`, the resulting game is saved.
+
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+use hexrails::{
+    headless_app,
+    replay::{CommandLog, FixedTick, Replay},
+    savegame::{self, SaveFormat},
+};
+use serde_json::Value;
+
+const USAGE: &str =
+    "Usage: hexrails-replay <start savegame> <log> [--expect <end savegame>] [--output <file>]";
+
+struct Args {
+    start: PathBuf,
+    log: PathBuf,
+    expect: Option<PathBuf>,
+    output: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut positional = Vec::new();
+        let mut expect = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--expect" => expect = Some(args.next().ok_or("--expect needs a file")?.into()),
+                "--output" => output = Some(args.next().ok_or("--output needs a file")?.into()),
+                "-h" | "--help" => return Ok(None),
+                other if other.starts_with('-') => return Err(format!("Unknown option {other}")),
+                other => positional.push(PathBuf::from(other)),
+            }
+        }
+        let [start, log]: [PathBuf; 2] = positional
+            .try_into()
+            .map_err(|_| "Expected a savegame and a log")?;
+        Ok(Some(Args {
+            start,
+            log,
+            expect,
+            output,
+        }))
+    }
+}
+
+fn run(args: Args) -> Result<bool, Box<dyn Error>> {
+    let log = CommandLog::read(&args.log)?;
+    println!(
+        "Replaying {} commands in {} ticks",
+        log.entries.len(),
+        log.end_tick.0
+    );
+
+    let mut app = headless_app();
+    // The first update runs the startup schedules, the game is loaded into a running app as well
+    app.update();
+    savegame::load_file(app.world_mut(), &args.start)?;
+    app.insert_resource(Replay::play(log));
+    while !app
+        .world()
+        .resource::<Replay>()
+        .is_finished(*app.world().resource::<FixedTick>())
+    {
+        app.update();
+    }
+
+    if let Some(output) = &args.output {
+        let format = SaveFormat::from_path(output).unwrap_or_default();
+        savegame::save_file(app.world_mut(), output, format)?;
+        println!("Wrote {} as {format:?}", output.display());
+    }
+
+    let Some(expect) = &args.expect else {
+        return Ok(true);
+    };
+    let mut actual = savegame::to_json(app.world_mut())?;
+    let mut expected = savegame::read_json(expect)?;
+    for savegame in [&mut actual, &mut expected] {
+        ignore_selection(savegame);
+    }
+    if actual == expected {
+        println!("The replay matches {}", expect.display());
+        Ok(true)
+    } else {
+        println!("The replay differs from {}", expect.display());
+        Ok(false)
+    }
+}
+
+fn ignore_selection(savegame: &mut Value) {
+    if let Some(trains) = savegame["trains"].as_array_mut() {
+        for train in trains {
+            train["player_controlled"] = Value::Bool(false);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Inspects, validates and repairs savegames without starting the game.
+//!
+//! ```text
+//! hexrails-save <savegame> [--repair] [--pretty] [--output <file>] [--format json|hxrb]
+//! ```
+//!
+//! Prints a summary and all broken invariants, exiting with an error if there are any.
+//! With `--repair`, they are fixed and the savegame is written back, to `--output` if given.
+//! With `--pretty`, the upgraded savegame is printed as indented JSON. `--format` chooses the
+//! format written, by default the one of the output file's extension or of the savegame.
+
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+use hexrails::savegame::{check::SaveFile, SaveFormat};
+
+const USAGE: &str =
+    "Usage: hexrails-save <savegame> [--repair] [--pretty] [--output <file>] [--format json|hxrb]";
+
+struct Args {
+    path: PathBuf,
+    repair: bool,
+    pretty: bool,
+    output: Option<PathBuf>,
+    format: Option<SaveFormat>,
+}
+
+impl Args {
+    /// Returns `None` if only the usage was asked for.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut path = None;
+        let mut parsed = Args {
+            path: PathBuf::new(),
+            repair: false,
+            pretty: false,
+            output: None,
+            format: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--repair" => parsed.repair = true,
+                "--pretty" => parsed.pretty = true,
+                "--output" => {
+                    let output = args.next().ok_or("--output needs a file")?;
+                    parsed.output = Some(output.into());
+                }
+                "--format" => {
+                    let format = args.next().ok_or("--format needs a format")?;
+                    parsed.format = Some(
+                        SaveFormat::from_extension(&format)
+                            .ok_or(format!("Unknown format {format}"))?,
+                    );
+                }
+                "-h" | "--help" => return Ok(None),
+                other if other.starts_with('-') => return Err(format!("Unknown option {other}")),
+                other if path.is_none() => path = Some(PathBuf::from(other)),
+                other => return Err(format!("Unexpected argument {other}")),
+            }
+        }
+        parsed.path = path.ok_or("No savegame given")?;
+        if parsed.format.is_some() && !parsed.repair && parsed.output.is_none() {
+            return Err("--format needs --repair or --output".to_string());
+        }
+        Ok(Some(parsed))
+    }
+}
+
+fn run(args: Args) -> Result<bool, Box<dyn Error>> {
+    let mut file = SaveFile::read(&args.path)?;
+    let graph = &file.rail_graph().graph;
+    println!("{}", args.path.display());
+    println!("  format:   {:?}", file.format);
+    println!("  version:  v{}", file.version);
+    println!(
+        "  network:  {} joints, {} edges",
+        graph.node_count(),
+        graph.edge_count()
+    );
+    println!("  trains:   {}", file.train_count());
+    println!("  stations: {}", file.station_count());
+
+    let problems = if args.repair {
+        file.repair()?
+    } else {
+        file.check()
+    };
+    for problem in &problems {
+        if args.repair {
+            println!("  repaired: {problem}, {}", problem.repair_description());
+        } else {
+            println!("  problem:  {problem}");
+        }
+    }
+    if problems.is_empty() {
+        println!("  no problems found");
+    }
+
+    if args.repair || args.output.is_some() {
+        let output = args.output.as_deref().unwrap_or(&args.path);
+        let format = args
+            .format
+            .or_else(|| SaveFormat::from_path(output))
+            .unwrap_or(file.format);
+        file.write(output, format)?;
+        println!("Wrote {} as {format:?}", output.display());
+    }
+    if args.pretty {
+        println!("{}", file.to_pretty_json()?);
+    }
+    Ok(args.repair || problems.is_empty())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! Blueprints, i.e. copies of rail layouts which can be pasted elsewhere.
+//!
+//! In building mode:
+//! - <kbd>8</kbd> selects copying: dragging a box with the left mouse button copies the tracks
+//!   in the tiles inside into a new [`Blueprint`], which is saved next to the savegames and
+//!   selected for pasting,
+//! - <kbd>9</kbd> selects pasting: a click lays the tracks of the blueprint around the clicked
+//!   tile, <kbd>R</kbd> rotates it by a sixth turn, <kbd>M</kbd> mirrors it and
+//!   <kbd>B</kbd> loads the next saved blueprint.
+//!
+//! Only the tracks are copied, not their speed limits, signals or stations.
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{color::palettes, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::commands::SimCommand;
+use crate::input::{BuildAction, BuildInput, BuildingState, MenuState};
+use crate::interact::{CursorWorldPos, TileClickEvent};
+use crate::railroad::{track_preview_sprite, BuildPreview, RailGraph, Track};
+use crate::savegame::{write_atomically, SAVEGAME_DIR};
+use crate::sprites::SpriteAssets;
+use crate::tilemap::{Joint, Tile, TILE_SCALE, TILE_WIDTH};
+
+pub struct BlueprintPlugin;
+impl Plugin for BlueprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlueprintTool>()
+            .add_systems(
+                Update,
+                (
+                    select_blueprint.run_if(in_state(BuildingState::CopyBlueprint)),
+                    (transform_blueprint, paste_blueprint)
+                        .run_if(in_state(BuildingState::PasteBlueprint)),
+                )
+                    .run_if(in_state(MenuState::Building)),
+            )
+            .add_systems(PostUpdate, update_blueprint_preview);
+    }
+}
+
+/// A rail layout, with the tiles relative to its origin `Tile(0, 0)`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Blueprint {
+    /// Every double track once.
+    pub tracks: Vec<Track>,
+}
+
+/// Where and how a [`Blueprint`] is pasted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// The tile the origin of the blueprint is placed at.
+    pub origin: Tile,
+    /// Counterclockwise sixth turns around the origin.
+    pub rotation: u8,
+    /// Whether the blueprint is mirrored along the east-west axis before rotating it.
+    pub mirrored: bool,
+}
+
+impl Blueprint {
+    /// Copies the tracks in the tiles for which `contains` is true, relative to `origin`.
+    pub fn capture(rail_graph: &RailGraph, origin: Tile, contains: impl Fn(Tile) -> bool) -> Self {
+        let tracks = rail_graph
+            .graph
+            .all_edges()
+            .map(|(from, to, _)| {
+                Track::from_joints(from, to).expect("Invariant: graph only has track edges")
+            })
+            .filter(|track| track.is_canonical_orientation() && contains(track.joint.tile))
+            .map(|track| translated(track, origin, Tile(0, 0)))
+            .collect();
+        Self { tracks }
+    }
+
+    /// Returns the tracks of the blueprint as placed by `placement`.
+    pub fn place(&self, placement: Placement) -> Vec<Track> {
+        self.tracks
+            .iter()
+            .map(|&track| {
+                let track = if placement.mirrored {
+                    track.mirrored()
+                } else {
+                    track
+                };
+                let track = track.rotated(placement.rotation.into());
+                translated(track, Tile(0, 0), placement.origin)
+            })
+            .collect()
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Moves `track` such that the tile `from` ends up at `to`.
+fn translated(track: Track, from: Tile, to: Tile) -> Track {
+    Track {
+        joint: Joint {
+            tile: track.joint.tile - from + to,
+            side: track.joint.side,
+        },
+        heading: track.heading,
+    }
+}
+
+/// The directory with the saved blueprints, next to the savegames.
+pub fn blueprint_dir() -> PathBuf {
+    PathBuf::from(SAVEGAME_DIR).join("blueprints")
+}
+
+/// Returns the file of the blueprint `name`.
+pub fn blueprint_path(name: &str) -> PathBuf {
+    blueprint_dir().join(format!("{name}.json"))
+}
+
+/// Returns the names of all saved blueprints, sorted.
+pub fn saved_blueprints() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(blueprint_dir()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns a name no saved blueprint has yet.
+fn new_blueprint_name() -> String {
+    (1..)
+        .map(|n| format!("blueprint-{n}"))
+        .find(|name| !blueprint_path(name).exists())
+        .expect("there are fewer blueprints than numbers")
+}
+
+/// The state of the blueprint tools.
+#[derive(Resource, Debug, Default)]
+struct BlueprintTool {
+    /// The blueprint to paste and its name.
+    current: Option<(String, Blueprint)>,
+    rotation: u8,
+    mirrored: bool,
+    /// The tile where dragging the selection box started, while the button is held.
+    selection_start: Option<Tile>,
+}
+
+impl BlueprintTool {
+    fn placement(&self, origin: Tile) -> Placement {
+        Placement {
+            origin,
+            rotation: self.rotation,
+            mirrored: self.mirrored,
+        }
+    }
+
+    fn select(&mut self, name: String, blueprint: Blueprint) {
+        info!("Blueprint {name}: {} tracks", blueprint.tracks.len());
+        self.current = Some((name, blueprint));
+        self.rotation = 0;
+        self.mirrored = false;
+    }
+
+    /// Loads the saved blueprint after the current one, or the first one.
+    fn load_next(&mut self) {
+        let names = saved_blueprints();
+        let current = self.current.as_ref().map(|(name, _)| name);
+        let next = match current.and_then(|current| names.iter().position(|n| n == current)) {
+            Some(index) => names.get(index + 1).or(names.first()),
+            None => names.first(),
+        };
+        let Some(name) = next.cloned() else {
+            info!("There are no blueprints in {}", blueprint_dir().display());
+            return;
+        };
+        match Blueprint::read(&blueprint_path(&name)) {
+            Ok(blueprint) => self.select(name, blueprint),
+            Err(err) => error!("Cannot load blueprint {name}: {err}"),
+        }
+    }
+}
+
+/// Returns the corners of the box spanned by the centers of the tiles `start` and `end`.
+fn selection_box(start: Tile, end: Tile) -> (Vec2, Vec2) {
+    let (start, end) = (start.world_pos(), end.world_pos());
+    // Some slack for the rounding of the tile positions
+    let slack = Vec2::splat(0.01);
+    (start.min(end) - slack, start.max(end) + slack)
+}
+
+// ================================ SYSTEMS ===================================
+
+/// This system lets the player drag a box over tiles, and copies the tracks in them into a
+/// new blueprint when the mouse button is released.
+fn select_blueprint(
+    mut click_event: MessageReader<TileClickEvent>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    cursor: Res<CursorWorldPos>,
+    rail_graph: Res<RailGraph>,
+    mut tool: ResMut<BlueprintTool>,
+    mut next_state: ResMut<NextState<BuildingState>>,
+    mut gizmos: Gizmos,
+) {
+    for evt in click_event.read() {
+        if evt.button == MouseButton::Left {
+            tool.selection_start = Some(evt.coord);
+        }
+    }
+    let Some(start) = tool.selection_start else {
+        return;
+    };
+    let end = cursor.0.map_or(start, Tile::from_world_pos);
+    let (min, max) = selection_box(start, end);
+    if mouse_input.pressed(MouseButton::Left) {
+        // Enlarged to cover the whole tiles at the corners
+        let size = max - min + Vec2::new(TILE_WIDTH, TILE_SCALE);
+        let center = Isometry2d::from_translation((min + max) / 2.);
+        gizmos.rect_2d(center, size, palettes::basic::AQUA);
+        return;
+    }
+
+    tool.selection_start = None;
+    let contains = |tile: Tile| {
+        let position = tile.world_pos();
+        position.cmpge(min).all() && position.cmple(max).all()
+    };
+    let origin = Tile::from_world_pos((min + max) / 2.);
+    let blueprint = Blueprint::capture(&rail_graph, origin, contains);
+    if blueprint.tracks.is_empty() {
+        info!("There are no tracks to copy");
+        return;
+    }
+    let name = new_blueprint_name();
+    match blueprint.write(&blueprint_path(&name)) {
+        Ok(()) => info!("Saved blueprint {name}"),
+        Err(err) => error!("Cannot save blueprint {name}: {err}"),
+    }
+    tool.select(name, blueprint);
+    next_state.set(BuildingState::PasteBlueprint);
+}
+
+fn transform_blueprint(input: Single<&BuildInput>, mut tool: ResMut<BlueprintTool>) {
+    if input.just_pressed(&BuildAction::RotateBlueprint) {
+        tool.rotation = (tool.rotation + 1) % 6;
+    }
+    if input.just_pressed(&BuildAction::MirrorBlueprint) {
+        tool.mirrored = !tool.mirrored;
+    }
+    if input.just_pressed(&BuildAction::NextBlueprint) {
+        tool.load_next();
+    }
+}
+
+/// This system lays the tracks of the current blueprint around the clicked tile,
+/// all with one [`SimCommand`].
+fn paste_blueprint(
+    mut click_event: MessageReader<TileClickEvent>,
+    tool: Res<BlueprintTool>,
+    mut sim_commands: MessageWriter<SimCommand>,
+) {
+    for evt in click_event.read() {
+        if evt.button != MouseButton::Left {
+            continue;
+        }
+        let Some((_, blueprint)) = &tool.current else {
+            info!("There is no blueprint to paste, copy one first or load one with B");
+            continue;
+        };
+        let tracks = blueprint.place(tool.placement(evt.coord));
+        sim_commands.write(SimCommand::LayTracks(tracks));
+    }
+}
+
+/// Marks the translucent sprites of the blueprint to paste.
+#[derive(Component)]
+struct BlueprintPreview;
+
+/// This system shows the current blueprint at the cursor while pasting, green where tracks
+/// would be built and red where they can't. It is respawned whenever anything changes.
+fn update_blueprint_preview(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    (menu_state, building_state): (Res<State<MenuState>>, Res<State<BuildingState>>),
+    (tool, cursor): (Res<BlueprintTool>, Res<CursorWorldPos>),
+    build_preview: BuildPreview,
+    previews: Query<Entity, With<BlueprintPreview>>,
+    mut shown: Local<Option<Placement>>,
+) {
+    let pasting = *menu_state.get() == MenuState::Building
+        && *building_state.get() == BuildingState::PasteBlueprint
+        && tool.current.is_some();
+    let placement = cursor
+        .0
+        .filter(|_| pasting)
+        .map(|position| tool.placement(Tile::from_world_pos(position)));
+    if placement == *shown && !tool.is_changed() && !build_preview.rail_graph.is_changed() {
+        return;
+    }
+    *shown = placement;
+
+    for preview in &previews {
+        commands.entity(preview).despawn();
+    }
+    let (Some(placement), Some((_, blueprint))) = (placement, &tool.current) else {
+        return;
+    };
+    for track in blueprint.place(placement) {
+        if build_preview.rail_graph.contains_track(track) {
+            continue;
+        }
+        commands.spawn((
+            track_preview_sprite(&assets, track, build_preview.can_build(track)),
+            Name::new("Blueprint preview"),
+            BlueprintPreview,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::railroad::TrackType;
+    use crate::tilemap::Direction;
+
+    const ORIGIN: Tile = Tile(1, 0);
+
+    /// A straight track with a switch at its end, to a curve and a straight track to the left
+    /// and a curve and a straight track to the right.
+    fn layout() -> Vec<Track> {
+        let track = |joint, heading| Track { joint, heading };
+        let start = track(
+            Joint {
+                tile: Tile(0, 0),
+                side: Direction::EAST,
+            },
+            TrackType::Straight,
+        );
+        let left = track(start.end_joint(), TrackType::CurvedLeft);
+        let right = track(start.end_joint(), TrackType::CurvedRight);
+        vec![
+            start,
+            left,
+            track(left.end_joint(), TrackType::Straight),
+            right,
+            track(right.end_joint(), TrackType::Straight),
+        ]
+    }
+
+    fn rail_graph(tracks: &[Track]) -> RailGraph {
+        let mut rail_graph = RailGraph::default();
+        for &track in tracks {
+            assert!(
+                rail_graph.add_double_track(track),
+                "{track:?} is laid twice"
+            );
+        }
+        rail_graph
+    }
+
+    /// The tracks in the orientation a [`Blueprint`] has them in, sorted.
+    fn canonical(tracks: &[Track]) -> Vec<Track> {
+        let mut tracks = tracks
+            .iter()
+            .map(|&track| {
+                if track.is_canonical_orientation() {
+                    track
+                } else {
+                    track.reversed()
+                }
+            })
+            .collect::<Vec<_>>();
+        tracks.sort_by_key(|track| (track.joint, track.heading as u8));
+        tracks
+    }
+
+    #[test]
+    fn captured_tracks_are_placed_where_they_were() {
+        let tracks = layout();
+        let blueprint = Blueprint::capture(&rail_graph(&tracks), ORIGIN, |_| true);
+        assert_eq!(blueprint.tracks.len(), tracks.len());
+
+        let placed = blueprint.place(Placement {
+            origin: ORIGIN,
+            ..default()
+        });
+        assert_eq!(canonical(&placed), canonical(&tracks));
+    }
+
+    #[test]
+    fn only_tracks_in_the_selection_are_captured() {
+        let tracks = layout();
+        let start_tile = tracks[0].joint.tile;
+        let blueprint = Blueprint::capture(&rail_graph(&tracks), ORIGIN, |tile| tile == start_tile);
+        assert_eq!(
+            blueprint.place(Placement {
+                origin: ORIGIN,
+                ..default()
+            }),
+            canonical(&tracks[..1])
+        );
+    }
+
+    #[test]
+    fn placed_blueprints_stay_connected() {
+        let tracks = layout();
+        let original = rail_graph(&tracks);
+        let blueprint = Blueprint::capture(&original, ORIGIN, |_| true);
+        // The tracks to route along, relative to the origin of the blueprint like its tracks
+        let ends = Blueprint {
+            tracks: [tracks[0], tracks[2], tracks[4]]
+                .map(|track| translated(track, ORIGIN, Tile(0, 0)))
+                .to_vec(),
+        };
+        let routes = |rail_graph: &RailGraph, ends: &[Track]| {
+            [ends[1], ends[2]].map(|end| {
+                rail_graph
+                    .find_route(ends[0].joint, end.end_joint())
+                    .map(|route| route.len())
+            })
+        };
+        let expected_routes = routes(&original, &tracks);
+        assert_eq!(expected_routes, [Some(4), Some(4)]);
+
+        for rotation in 0..6 {
+            for mirrored in [false, true] {
+                let placement = Placement {
+                    origin: Tile(3, -2),
+                    rotation,
+                    mirrored,
+                };
+                let placed = rail_graph(&blueprint.place(placement));
+                assert_eq!(
+                    placed.graph.edge_count(),
+                    original.graph.edge_count(),
+                    "{placement:?}"
+                );
+                let ends = ends.place(placement);
+                assert!(placed.is_switch(ends[0].end_joint()), "{placement:?}");
+                assert_eq!(routes(&placed, &ends[..]), expected_routes, "{placement:?}");
+            }
+        }
+    }
+}
@@ -2,14 +2,15 @@
 //!
 //! Mostly copy pasted code + using [`bevy_pancam`] for the zoom and drag functionality.
 //!
-//! <kbd>W</kbd>, <kbd>A</kbd>, <kbd>S</kbd>, <kbd>D</kbd> or <kbd>MMB</kbd>/<kbd>RMB</kbd> to move the camera.
+//! <kbd>W</kbd>, <kbd>A</kbd>, <kbd>S</kbd>, <kbd>D</kbd> or <kbd>LMB</kbd>/<kbd>MMB</kbd> to move the camera.
+//! In building mode only <kbd>MMB</kbd> drags the camera, since <kbd>LMB</kbd> drags out tracks.
 //! Scroll to zoom in/out.
 
 use bevy::camera::ScalingMode;
 use bevy::prelude::*;
 use bevy_pancam::{PanCam, PanCamPlugin};
 
-use crate::input::{CameraAction, CameraInput};
+use crate::input::{CameraAction, CameraInput, MenuState};
 use crate::ASPECT_RATIO;
 
 pub struct MovingCameraPlugin;
@@ -18,7 +19,11 @@ impl Plugin for MovingCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(PanCamPlugin)
             .add_systems(Startup, spawn_camera)
-            .add_systems(Update, camera_2d_movement_system);
+            .add_systems(Update, camera_2d_movement_system)
+            .add_systems(
+                Update,
+                update_grab_buttons.run_if(state_changed::<MenuState>),
+            );
     }
 }
 
@@ -139,3 +144,11 @@ fn camera_2d_movement_system(
         transform.translation += Vec3::new(options.velocity.x, options.velocity.y, 0.0);
     }
 }
+
+/// In building mode the left mouse button drags out tracks instead of the camera.
+fn update_grab_buttons(state: Res<State<MenuState>>, mut pancam: Single<&mut PanCam>) {
+    pancam.grab_buttons = match state.get() {
+        MenuState::Building => vec![MouseButton::Middle],
+        _ => vec![MouseButton::Left, MouseButton::Middle],
+    };
+}
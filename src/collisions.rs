@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_rapier2d::prelude::CollisionEvent;
+use bevy_rapier2d::{plugin::PhysicsSet, prelude::CollisionEvent};
 
 use crate::trains::{Crashed, TrainIndex, VehicleOf, VehicleType, Velocity};
 
@@ -7,8 +7,12 @@ pub struct CollisionPlugin;
 
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-        // PostUpdate so that velocity shouldn't be overwritten after being zeroed.
-        app.add_systems(PostUpdate, crash_on_collision);
+        // Right after the physics step, which runs in the fixed schedule as well,
+        // so that velocity shouldn't be overwritten after being zeroed.
+        app.add_systems(
+            FixedPostUpdate,
+            crash_on_collision.after(PhysicsSet::Writeback),
+        );
     }
 }
 
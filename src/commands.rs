@@ -0,0 +1,346 @@
+//! Commands changing the simulation, which the player issues by clicking or pressing keys.
+//!
+//! The systems reading the input don't change the world directly, instead they write a
+//! [`SimCommand`], which is applied at the start of the next `FixedUpdate` step.
+//! This way every change happens at a well defined fixed tick and can be recorded and
+//! replayed, see [`replay`](crate::replay), and most of them can be undone, see
+//! [`history`](crate::history).
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::autopilot::Autopilot;
+use crate::driving::Steering;
+use crate::history::{self, Action, History};
+use crate::orders::{Order, Orders};
+use crate::railroad::{demolish_tracks, lay_track, RailGraph, Track, TrackType};
+use crate::replay::{FixedTick, Replay};
+use crate::stations::toggle_station;
+use crate::tilemap::Joint;
+use crate::trainbuilder::{
+    append_vehicle, couple_trains, remove_vehicle, rerail_train, spawn_train, uncouple,
+};
+use crate::trains::*;
+
+/// Applies the [`SimCommand`]s, part of the [`SimulationPlugins`](crate::SimulationPlugins).
+pub struct CommandsPlugin;
+
+impl Plugin for CommandsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SimCommand>()
+            .configure_sets(FixedUpdate, CommandSet.before(ControlSet::Drive))
+            .add_systems(FixedUpdate, apply_commands.in_set(CommandSet));
+    }
+}
+
+/// The system in `FixedUpdate` applying the [`SimCommand`]s, before the trains are driven.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CommandSet;
+
+/// A change to the simulation, issued by the player.
+///
+/// Trains are referred to by their [`TrainId`], since entities differ between runs.
+#[derive(Message, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SimCommand {
+    /// Lays a double track, or selects it at a switch if it already exists.
+    LayTrack(Track),
+    /// Lays several tracks at once, e.g. dragged out in building mode.
+    /// Unlike [`SimCommand::LayTrack`], existing tracks are left as they are.
+    LayTracks(Vec<Track>),
+    /// Places or removes a signal.
+    ToggleSignal(Joint),
+    /// Sets the speed limit of the tracks from this joint to the next step.
+    CycleSpeedLimit(Joint),
+    /// Removes all tracks from this joint, which no train stands on.
+    Demolish(Joint),
+    /// Spawns a new train with a single vehicle, driving from `joint`.
+    SpawnTrain {
+        joint: Joint,
+        vehicle_type: VehicleType,
+    },
+    /// Despawns a whole train with all its vehicles.
+    DespawnTrain(TrainId),
+    /// Appends a vehicle to the back of a train.
+    AppendVehicle {
+        train: TrainId,
+        vehicle_type: VehicleType,
+    },
+    /// Couples the end `second_end` of the train `second` to the end `first_end` of `first`.
+    Couple {
+        first: TrainId,
+        first_end: BumperNode,
+        second: TrainId,
+        second_end: BumperNode,
+    },
+    /// Splits a train in two, the vehicles from `index` on become a new train.
+    Uncouple {
+        train: TrainId,
+        index: u16,
+    },
+    /// Removes the vehicle at `index` from a train, the ones behind it become a new train.
+    RemoveVehicle {
+        train: TrainId,
+        index: u16,
+    },
+    /// Stops a (crashed) train and fits it onto the tracks it is standing on.
+    Rerail(TrainId),
+    /// Sets throttle and brake of a train, taking over from its [`Autopilot`].
+    SetController {
+        train: TrainId,
+        controller: Controller,
+    },
+    /// Takes the track `direction` at the switches the train reaches from now on,
+    /// `None` to follow them again.
+    Steer {
+        train: TrainId,
+        direction: Option<TrackType>,
+    },
+    /// Reverses a standing train.
+    Reverse(TrainId),
+    /// Sends a train to `destination` with the [`Autopilot`].
+    SetDestination {
+        train: TrainId,
+        destination: Joint,
+    },
+    /// Appends stops to the orders of a train.
+    AppendOrders {
+        train: TrainId,
+        orders: Vec<Order>,
+        /// Whether all orders of the train start over after the last one.
+        repeat: bool,
+    },
+    ClearOrders(TrainId),
+    /// Adds the track at `joint` to an adjacent station or builds a new one there,
+    /// or removes it from its station.
+    ToggleStation(Joint),
+    SetSpeedLimitMode(SpeedLimitMode),
+    /// Reverts the last [`Action`] in the [`History`].
+    Undo,
+    /// Applies the last undone [`Action`] again.
+    Redo,
+}
+
+impl SimCommand {
+    /// Changes the world according to the command, and records it in the [`History`]
+    /// if it can be undone.
+    pub fn apply(self, world: &mut World) {
+        match self {
+            SimCommand::Undo => history::undo(world),
+            SimCommand::Redo => history::redo(world),
+            command => {
+                let Some(action) = Action::apply(command, world) else {
+                    return;
+                };
+                if let Some(mut history) = world.get_resource_mut::<History>() {
+                    history.record(action);
+                }
+            }
+        }
+    }
+
+    /// Changes the world according to the command, without recording it.
+    ///
+    /// Commands which can't be applied anymore, e.g. for a train which has been
+    /// despawned in the meantime, are ignored.
+    pub(crate) fn execute(self, world: &mut World) {
+        let result = match self {
+            SimCommand::LayTrack(track) => world.run_system_once_with(lay_track, track),
+            SimCommand::LayTracks(tracks) => tracks.into_iter().try_for_each(|track| {
+                if world.resource::<RailGraph>().contains_track(track) {
+                    return Ok(());
+                }
+                world.run_system_once_with(lay_track, track)
+            }),
+            SimCommand::ToggleSignal(joint) => {
+                world.resource_mut::<RailGraph>().toggle_signal(joint);
+                Ok(())
+            }
+            SimCommand::CycleSpeedLimit(joint) => {
+                world.resource_mut::<RailGraph>().cycle_speed_limit(joint);
+                Ok(())
+            }
+            SimCommand::Demolish(joint) => world.run_system_once_with(demolish_tracks, joint),
+            SimCommand::SpawnTrain {
+                joint,
+                vehicle_type,
+            } => world.run_system_once_with(spawn_train, (joint, vehicle_type)),
+            SimCommand::DespawnTrain(train) => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                info!("Despawning train {train:?}");
+                // Also despawns all the vehicles.
+                world.entity_mut(train).despawn();
+                Ok(())
+            }
+            SimCommand::AppendVehicle {
+                train,
+                vehicle_type,
+            } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                world.run_system_once_with(append_vehicle, (train, vehicle_type))
+            }
+            SimCommand::Couple {
+                first,
+                first_end,
+                second,
+                second_end,
+            } => {
+                let (Some(first), Some(second)) =
+                    (find_train(world, first), find_train(world, second))
+                else {
+                    return;
+                };
+                world.run_system_once_with(couple_trains, (first, first_end, second, second_end))
+            }
+            SimCommand::Uncouple { train, index } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                world.run_system_once_with(uncouple, (train, index))
+            }
+            SimCommand::RemoveVehicle { train, index } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                world.run_system_once_with(remove_vehicle, (train, index))
+            }
+            SimCommand::Rerail(train) => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                world.run_system_once_with(rerail_train, train)
+            }
+            SimCommand::SetController { train, controller } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                let mut train = world.entity_mut(train);
+                train.remove::<Autopilot>();
+                if let Some(mut current) = train.get_mut::<Controller>() {
+                    *current = controller;
+                }
+                Ok(())
+            }
+            SimCommand::Steer { train, direction } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                let mut train = world.entity_mut(train);
+                match direction {
+                    Some(direction) => train.insert(Steering(direction)),
+                    None => train.remove::<Steering>(),
+                };
+                Ok(())
+            }
+            SimCommand::Reverse(train) => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                let is_standing = world
+                    .get::<Velocity>(train)
+                    .is_some_and(|velocity| velocity.velocity == 0.);
+                if !is_standing {
+                    warn!("Cannot reverse moving train!");
+                    return;
+                }
+                // The planned route doesn't make sense anymore
+                world.entity_mut(train).remove::<Autopilot>();
+                world.run_system_once_with(reverse_train, train)
+            }
+            SimCommand::SetDestination { train, destination } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                world.entity_mut(train).insert(Autopilot { destination });
+                Ok(())
+            }
+            SimCommand::AppendOrders {
+                train,
+                orders,
+                repeat,
+            } => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                if let Some(mut current) = world.get_mut::<Orders>(train) {
+                    current.append(orders, repeat);
+                    info!("Orders: {:?}", current.orders);
+                }
+                Ok(())
+            }
+            SimCommand::ClearOrders(train) => {
+                let Some(train) = find_train(world, train) else {
+                    return;
+                };
+                if let Some(mut orders) = world.get_mut::<Orders>(train) {
+                    orders.clear();
+                    info!("Cleared orders");
+                }
+                Ok(())
+            }
+            SimCommand::ToggleStation(joint) => world.run_system_once_with(toggle_station, joint),
+            SimCommand::SetSpeedLimitMode(mode) => {
+                info!("Speed limit mode: {mode:?}");
+                world.insert_resource(mode);
+                Ok(())
+            }
+            command @ (SimCommand::Undo | SimCommand::Redo) => {
+                command.apply(world);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            error!("Applying a command failed: {e:?}");
+        }
+    }
+}
+
+/// Returns the train with the given id.
+pub fn find_train(world: &mut World, id: TrainId) -> Option<Entity> {
+    let train = world
+        .query::<(Entity, &TrainId)>()
+        .iter(world)
+        .find(|&(_, &train_id)| train_id == id)
+        .map(|(train, _)| train);
+    if train.is_none() {
+        warn!("There is no train {id:?} (anymore)");
+    }
+    train
+}
+
+// ================================ SYSTEMS ===================================
+
+/// System to apply the commands written since the last fixed step,
+/// or the ones of the replayed [`CommandLog`](crate::replay::CommandLog).
+fn apply_commands(
+    mut commands: Commands,
+    mut sim_commands: MessageReader<SimCommand>,
+    mut replay: ResMut<Replay>,
+    tick: Res<FixedTick>,
+) {
+    let due: Vec<SimCommand> = match replay.as_mut() {
+        Replay::Off => sim_commands.read().cloned().collect(),
+        Replay::Recording(log) => {
+            let due: Vec<SimCommand> = sim_commands.read().cloned().collect();
+            for command in &due {
+                log.record(*tick, command.clone());
+            }
+            due
+        }
+        Replay::Playing { log, next } => {
+            // The player only watches
+            sim_commands.clear();
+            log.due(*tick, next)
+                .iter()
+                .map(|entry| entry.command.clone())
+                .collect()
+        }
+    };
+
+    for command in due {
+        commands.queue(move |world: &mut World| command.apply(world));
+    }
+}
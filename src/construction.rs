@@ -0,0 +1,222 @@
+//! The rules where tracks can be built and what they cost, depending on the terrain.
+//!
+//! A track lies within the tile of its [`Track::joint`], which decides the rules:
+//! - tracks can't leave the map,
+//! - tracks on water are bridges and tracks on mountains tunnels, both only straight,
+//! - forests and hills make tracks more expensive, bridges and tunnels even more so.
+
+use std::fmt;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::railroad::{Track, TrackType};
+use crate::terrain::{TerrainMap, TerrainType};
+
+/// How a track is built, depending on the terrain below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Structure {
+    /// On the ground.
+    Embankment,
+    Bridge,
+    Tunnel,
+}
+
+/// Why a track can't be built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    OffMap,
+    /// Bridges are disabled in the [`BuildRules`], or the track isn't straight.
+    NoBridge,
+    /// Tunnels are disabled in the [`BuildRules`], or the track isn't straight.
+    NoTunnel,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::OffMap => write!(f, "the tile is not on the map"),
+            BuildError::NoBridge => write!(f, "water can only be crossed by straight bridges"),
+            BuildError::NoTunnel => write!(f, "mountains can only be crossed by straight tunnels"),
+        }
+    }
+}
+
+/// A track which may be built, see [`BuildRules::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildPermit {
+    pub structure: Structure,
+    pub cost: u64,
+}
+
+/// Decides where tracks can be built and what they cost.
+#[derive(Resource, Debug, Clone)]
+pub struct BuildRules {
+    /// The cost of a track on land, the other terrain types multiply it.
+    pub base_cost: u64,
+    pub allow_bridges: bool,
+    pub allow_tunnels: bool,
+}
+
+impl Default for BuildRules {
+    fn default() -> Self {
+        Self {
+            base_cost: 100,
+            allow_bridges: true,
+            allow_tunnels: true,
+        }
+    }
+}
+
+impl BuildRules {
+    /// Returns how `track` would be built and its cost, or why it can't be built.
+    pub fn check(&self, track: Track, terrain: &TerrainMap) -> Result<BuildPermit, BuildError> {
+        let terrain_type = terrain.get(track.joint.tile).ok_or(BuildError::OffMap)?;
+        let is_straight = track.heading == TrackType::Straight;
+        let (structure, cost_factor) = match terrain_type {
+            TerrainType::Land => (Structure::Embankment, 1),
+            TerrainType::Forest => (Structure::Embankment, 2),
+            TerrainType::Hills => (Structure::Embankment, 3),
+            TerrainType::Water if self.allow_bridges && is_straight => (Structure::Bridge, 8),
+            TerrainType::Water => return Err(BuildError::NoBridge),
+            TerrainType::Mountains if self.allow_tunnels && is_straight => (Structure::Tunnel, 12),
+            TerrainType::Mountains => return Err(BuildError::NoTunnel),
+        };
+        Ok(BuildPermit {
+            structure,
+            cost: cost_factor * self.base_cost,
+        })
+    }
+}
+
+/// The resources to build tracks with.
+#[derive(SystemParam)]
+pub struct Construction<'w> {
+    pub rules: Res<'w, BuildRules>,
+    pub terrain: Res<'w, TerrainMap>,
+    pub costs: ResMut<'w, ConstructionCosts>,
+}
+
+impl Construction<'_> {
+    /// Checks `track` against the [`BuildRules`], see [`BuildRules::check`].
+    pub fn check(&self, track: Track) -> Result<BuildPermit, BuildError> {
+        self.rules.check(track, &self.terrain)
+    }
+}
+
+/// The total cost of all tracks built so far.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstructionCosts(pub u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::MAP_RADIUS;
+    use crate::tilemap::{Joint, Tile};
+
+    const TERRAIN_TYPES: [TerrainType; 5] = [
+        TerrainType::Land,
+        TerrainType::Water,
+        TerrainType::Forest,
+        TerrainType::Hills,
+        TerrainType::Mountains,
+    ];
+
+    /// The map of the first seed, which has tiles of every terrain type.
+    fn terrain_map() -> TerrainMap {
+        (0..)
+            .map(|seed| TerrainMap::new(Some(seed)))
+            .find(|map| {
+                TERRAIN_TYPES
+                    .iter()
+                    .all(|&terrain_type| map.iter().any(|(_, t)| t == terrain_type))
+            })
+            .unwrap()
+    }
+
+    /// A track of `heading` in some tile of `terrain_type`.
+    fn track_on(map: &TerrainMap, terrain_type: TerrainType, heading: TrackType) -> Track {
+        let (tile, _) = map.iter().find(|&(_, t)| t == terrain_type).unwrap();
+        Track {
+            joint: Joint { tile, ..default() },
+            heading,
+        }
+    }
+
+    #[test]
+    fn tracks_cant_leave_the_map() {
+        let track = Track {
+            joint: Joint {
+                tile: Tile(MAP_RADIUS + 1, 0),
+                ..default()
+            },
+            heading: TrackType::Straight,
+        };
+        let rules = BuildRules::default();
+        assert_eq!(
+            rules.check(track, &TerrainMap::default()),
+            Err(BuildError::OffMap)
+        );
+    }
+
+    #[test]
+    fn terrain_decides_structure_and_cost() {
+        let map = terrain_map();
+        let rules = BuildRules {
+            base_cost: 10,
+            ..default()
+        };
+        let expected = [
+            (TerrainType::Land, Structure::Embankment, 10),
+            (TerrainType::Forest, Structure::Embankment, 20),
+            (TerrainType::Hills, Structure::Embankment, 30),
+            (TerrainType::Water, Structure::Bridge, 80),
+            (TerrainType::Mountains, Structure::Tunnel, 120),
+        ];
+        for (terrain_type, structure, cost) in expected {
+            let track = track_on(&map, terrain_type, TrackType::Straight);
+            assert_eq!(
+                rules.check(track, &map),
+                Ok(BuildPermit { structure, cost }),
+                "{terrain_type:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn curves_on_land_cost_the_same() {
+        let map = terrain_map();
+        let rules = BuildRules::default();
+        for terrain_type in [TerrainType::Land, TerrainType::Forest, TerrainType::Hills] {
+            let straight = track_on(&map, terrain_type, TrackType::Straight);
+            let curve = track_on(&map, terrain_type, TrackType::CurvedLeft);
+            assert_eq!(rules.check(curve, &map), rules.check(straight, &map));
+        }
+    }
+
+    #[test]
+    fn bridges_and_tunnels_are_straight() {
+        let map = terrain_map();
+        let rules = BuildRules::default();
+        for heading in [TrackType::CurvedLeft, TrackType::CurvedRight] {
+            let bridge = track_on(&map, TerrainType::Water, heading);
+            assert_eq!(rules.check(bridge, &map), Err(BuildError::NoBridge));
+            let tunnel = track_on(&map, TerrainType::Mountains, heading);
+            assert_eq!(rules.check(tunnel, &map), Err(BuildError::NoTunnel));
+        }
+    }
+
+    #[test]
+    fn bridges_and_tunnels_can_be_disabled() {
+        let map = terrain_map();
+        let rules = BuildRules {
+            allow_bridges: false,
+            allow_tunnels: false,
+            ..default()
+        };
+        let bridge = track_on(&map, TerrainType::Water, TrackType::Straight);
+        assert_eq!(rules.check(bridge, &map), Err(BuildError::NoBridge));
+        let tunnel = track_on(&map, TerrainType::Mountains, TrackType::Straight);
+        assert_eq!(rules.check(tunnel, &map), Err(BuildError::NoTunnel));
+    }
+}
@@ -4,13 +4,18 @@ use bevy::color::palettes;
 use bevy::prelude::*;
 use bevy_rapier2d::render::RapierDebugRenderPlugin;
 
+use crate::construction::ConstructionCosts;
+use crate::history::History;
 use crate::input::{
     BuildingState, DebugGizmosState, MenuAction, MenuInput, MenuState, SpawningState,
 };
 use crate::interact::{NodeClickEvent, TileClickEvent};
+use crate::orders::Orders;
 use crate::railroad::RailGraph;
+use crate::replay::{FixedTick, Replay};
+use crate::stations::Station;
 use crate::tilemap::{Joint, TILE_SCALE};
-use crate::trains::{PlayerControlledTrain, Trail, Velocity};
+use crate::trains::{PlayerControlledTrain, Trail, TrainMarker, Velocity};
 
 pub struct DebugPlugin;
 impl Plugin for DebugPlugin {
@@ -40,13 +45,48 @@ fn help_print_states(
     menu_state: Res<State<MenuState>>,
     build_state: Res<State<BuildingState>>,
     spawn_state: Res<State<SpawningState>>,
+    stations: Query<&Station>,
+    trains: Query<(Entity, &Trail, &Velocity), With<TrainMarker>>,
+    orders: Query<(Entity, &Orders)>,
+    (replay, tick, costs, history): (
+        Res<Replay>,
+        Res<FixedTick>,
+        Res<ConstructionCosts>,
+        Res<History>,
+    ),
 ) {
     if input.just_pressed(&MenuAction::Help) {
         match menu_state.get() {
             MenuState::Driving => info!("State: Driving"),
             MenuState::Building => info!("State: Building {:?}", build_state.get()),
             MenuState::Spawning => info!("State: Spawning {:?}", spawn_state.get()),
+            MenuState::SaveMenu => info!("State: Save menu"),
         };
+        info!("Tracks built for {} so far", costs.0);
+        info!(
+            "{} actions to undo, {} to redo",
+            history.undo_len(),
+            history.redo_len()
+        );
+        if let Replay::Recording(log) = replay.as_ref() {
+            info!(
+                "Recording: {} commands in {} ticks, F9 stops",
+                log.entries.len(),
+                tick.0
+            );
+        }
+        for station in &stations {
+            info!(
+                "{}: trains stopped {:?}",
+                station.name,
+                station.stopped_trains(&trains)
+            );
+        }
+        for (train, orders) in &orders {
+            if !orders.orders.is_empty() {
+                info!("Train {train:?}: {orders}");
+            }
+        }
     }
 }
 
@@ -1,15 +1,24 @@
 //! Module for manually driving a train, including selection, throttle, brake and reverse.
 
 use crate::{
+    autopilot::Autopilot,
+    commands::SimCommand,
     input::{DriveAction, DriveInput, MenuState},
     interact::TrainClickEvent,
-    railroad::{RailGraph, Track, TrackType},
+    railroad::{RailGraph, TrackType},
     trains::*,
 };
 
-use petgraph::EdgeDirection;
+use bevy::prelude::*;
 
-use bevy::{ecs::system::RunSystemOnce, prelude::*};
+/// Extends the paths of trains which aren't driven by the [`Autopilot`].
+pub struct DrivingPlugin;
+
+impl Plugin for DrivingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, auto_extend_train_path.in_set(ControlSet::Drive));
+    }
+}
 
 pub struct ManualDrivingPlugin;
 
@@ -17,7 +26,7 @@ impl Plugin for ManualDrivingPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (throttling_system, auto_extend_train_path, reverse_train_system)
+            (throttling_system, steering_system, reverse_train_system)
                 .run_if(in_state(MenuState::Driving)),
         )
         // An observer, so it reacts to the click during command-flush, strictly before
@@ -26,23 +35,20 @@ impl Plugin for ManualDrivingPlugin {
     }
 }
 
+/// The track a train takes at the switches it reaches, set with [`SimCommand::Steer`].
+/// Trains without it follow the switches.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Steering(pub TrackType);
+
 /// System to extend the path of trains if necessary. Useful mosty for manual driving.
+///
+/// Trains follow the switches, but a train with [`Steering`] sets the switch directly
+/// in front of it.
 fn auto_extend_train_path(
-    mut trains: Query<(&mut Trail, Option<&PlayerControlledTrain>)>,
-    input: Single<&DriveInput>,
-    graph_res: Res<RailGraph>,
+    mut trains: Query<(&mut Trail, Option<&Steering>), Without<Autopilot>>,
+    mut graph_res: ResMut<RailGraph>,
 ) {
-    let graph = &graph_res.graph;
-    let steer_value = input.value(&DriveAction::SwitchDirection);
-    let preferred_direction = if steer_value > 0.0 {
-        TrackType::CurvedRight
-    } else if steer_value < 0.0 {
-        TrackType::CurvedLeft
-    } else {
-        TrackType::Straight
-    };
-
-    for (mut train, is_player_controlled) in trains.iter_mut() {
+    for (mut train, steering) in trains.iter_mut() {
         let max_progress = (train.path.len() - 1) as f32;
         // todo: does this put a hard limit on the velocity of player controlled trains?
         // The 0.5 anticipates the future
@@ -52,26 +58,16 @@ fn auto_extend_train_path(
                 .last()
                 .expect("TrainHead::path invariant broken: contains no elements")
                 .clone();
-            let mut next_tile = None;
-            graph
-                .edges_directed(path_end, EdgeDirection::Outgoing)
-                .for_each(|(this, next, _edge)| {
-                    // Prefer input direction, if this train is steered by a player
-                    if is_player_controlled.is_some()
-                        && (Track::from_joints(this, next))
-                            .expect("Invariant: graph only has track edges")
-                            .heading
-                            == preferred_direction
-                    {
-                        next_tile = Some(next);
-                    } else if next_tile.is_none() {
-                        next_tile = Some(next);
-                    }
-                });
-
-            if let Some(next_tile) = next_tile {
-                train.path.push(next_tile);
-                if train.path.len() >= train.length as usize + 5 {
+
+            if let Some(&Steering(direction)) = steering
+                && graph_res.is_switch(path_end)
+            {
+                graph_res.set_switch(path_end, direction);
+            }
+
+            if let Some(next_track) = graph_res.route_from(path_end) {
+                train.path.push(next_track.end_joint());
+                if train.back_index() >= 3 {
                     // The remove operation is there to stop the path from growing continiously.
                     // But it does use O(n) time, but since n should stay constant this way, this
                     // is fine.
@@ -84,42 +80,92 @@ fn auto_extend_train_path(
 }
 
 /// System to set the acceleration of the player driven train
+///
+/// Using throttle or brake takes over from the [`Autopilot`].
+/// A command is only written when the pedals change, not every frame.
 fn throttling_system(
-    mut train: Query<&mut Controller, With<PlayerControlledTrain>>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    train: Query<(&TrainId, &Controller, Has<Autopilot>), With<PlayerControlledTrain>>,
     input: Single<&DriveInput>,
+    mut last_written: Local<Option<(TrainId, Controller)>>,
 ) {
-    for mut controller in train.iter_mut() {
-        controller.throttle = input.button_value(&DriveAction::Accelerate);
-        controller.brake = input.button_value(&DriveAction::Brake);
+    for (&id, &current, has_autopilot) in train.iter() {
+        let pedal_pressed =
+            input.pressed(&DriveAction::Brake) || input.pressed(&DriveAction::Accelerate);
+        if has_autopilot && !pedal_pressed {
+            *last_written = None;
+            continue;
+        }
 
-        // allow for somewhat of a one pedal drive
-        if !input.pressed(&DriveAction::Brake) && !input.pressed(&DriveAction::Accelerate) {
-            controller.brake = 0.1;
+        let controller = Controller {
+            throttle: input.button_value(&DriveAction::Accelerate),
+            // allow for somewhat of a one pedal drive
+            brake: if pedal_pressed {
+                input.button_value(&DriveAction::Brake)
+            } else {
+                0.1
+            },
+        };
+        // Until the command is applied, the train still has the old controller
+        if (controller == current && !has_autopilot) || *last_written == Some((id, controller)) {
+            continue;
         }
+        *last_written = Some((id, controller));
+        sim_commands.write(SimCommand::SetController {
+            train: id,
+            controller,
+        });
+    }
+}
+
+/// System to steer the player driven train at the switches ahead.
+///
+/// Like [`throttling_system`], a command is only written when the direction changes.
+fn steering_system(
+    mut sim_commands: MessageWriter<SimCommand>,
+    train: Query<(&TrainId, Option<&Steering>), With<PlayerControlledTrain>>,
+    input: Single<&DriveInput>,
+    mut last_written: Local<Option<(TrainId, Option<TrackType>)>>,
+) {
+    let steer_value = input.value(&DriveAction::SwitchDirection);
+    let direction = if steer_value > 0.0 {
+        Some(TrackType::CurvedRight)
+    } else if steer_value < 0.0 {
+        Some(TrackType::CurvedLeft)
+    } else {
+        None
+    };
+
+    for (&id, steering) in train.iter() {
+        // Until the command is applied, the train still has the old steering
+        let current = steering.map(|&Steering(direction)| direction);
+        if current == direction || *last_written == Some((id, direction)) {
+            continue;
+        }
+        *last_written = Some((id, direction));
+        sim_commands.write(SimCommand::Steer {
+            train: id,
+            direction,
+        });
     }
 }
 
 /// System to reverse a whole train on key press
 fn reverse_train_system(
-    mut commands: Commands,
-    trains: Query<(Entity, &Velocity), With<PlayerControlledTrain>>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    trains: Query<(&TrainId, &Velocity), With<PlayerControlledTrain>>,
     input: Single<&DriveInput>,
 ) {
     if !input.just_pressed(&DriveAction::Reverse) {
         return;
     }
     // FIXME: Allow the player to steer with controller.trim_front();
-    for (id, velocity) in trains.iter() {
+    for (&id, velocity) in trains.iter() {
         if velocity.velocity != 0. {
             warn!("Cannot reverse moving train!");
             continue;
         }
-
-        commands.queue(move |world: &mut World| {
-            if let Err(e) = world.run_system_once_with(reverse_train, id) {
-                error!("reverse_train failed: {e:?}");
-            }
-        });
+        sim_commands.write(SimCommand::Reverse(id));
     }
 }
 
@@ -127,14 +173,28 @@ fn reverse_train_system(
 fn train_selection_system(
     trigger: On<TrainClickEvent>,
     mut commands: Commands,
-    mut controlled_train: Query<(Entity, &mut Controller), With<PlayerControlledTrain>>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    controlled_train: Query<
+        (Entity, &TrainId, Has<Autopilot>, Has<Steering>),
+        With<PlayerControlledTrain>,
+    >,
 ) {
     let ev = trigger.event();
 
     // Remove player control from previously active train and release all control.
-    if let Ok((entity, mut control)) = controlled_train.single_mut() {
-        control.throttle = 0.0;
-        control.brake = 0.0;
+    if let Ok((entity, &id, has_autopilot, has_steering)) = controlled_train.single() {
+        if !has_autopilot {
+            sim_commands.write(SimCommand::SetController {
+                train: id,
+                controller: Controller::default(),
+            });
+        }
+        if has_steering {
+            sim_commands.write(SimCommand::Steer {
+                train: id,
+                direction: None,
+            });
+        }
         commands.entity(entity).try_remove::<PlayerControlledTrain>();
     }
 
@@ -0,0 +1,696 @@
+//! Undoing and redoing the player's actions.
+//!
+//! Applying a [`SimCommand`] which builds on the railroad or composes trains records an
+//! [`Action`] in the [`History`], which knows how to revert exactly the changes the command
+//! made. Undoing and redoing are commands themselves, [`SimCommand::Undo`] and
+//! [`SimCommand::Redo`], so they happen at a fixed tick and are replayed like any other command.
+//!
+//! Driving isn't recorded, since the trains have moved on in the meantime anyway.
+//! For the same reason, undoing coupling or uncoupling only works while the trains are
+//! still standing where they were (un)coupled, and built tracks can't be removed while a train
+//! stands on them. Such actions stay in the history. The history is cleared on load.
+
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::system::{RunSystemError, RunSystemOnce},
+    prelude::*,
+};
+
+use crate::commands::{find_train, SimCommand};
+use crate::construction::ConstructionCosts;
+use crate::input::{MenuAction, MenuInput};
+use crate::railroad::{demolish_exact_tracks, RailGraph, Track, TrackType};
+use crate::tilemap::Joint;
+use crate::trainbuilder::uncouple;
+use crate::trains::*;
+
+/// The number of actions which can be undone.
+const HISTORY_LIMIT: usize = 100;
+
+/// Provides the [`History`], part of the [`SimulationPlugins`](crate::SimulationPlugins).
+pub struct HistoryPlugin;
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>();
+    }
+}
+
+/// Undo and redo with <kbd>Ctrl</kbd>+<kbd>Z</kbd> and <kbd>Ctrl</kbd>+<kbd>Y</kbd>.
+pub struct HistoryUiPlugin;
+impl Plugin for HistoryUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, undo_redo_system);
+    }
+}
+
+/// The actions which can be undone, and the undone ones which can be redone.
+#[derive(Resource, Debug, Default)]
+pub struct History {
+    /// The most recent action last, at most [`HISTORY_LIMIT`].
+    undo: VecDeque<Action>,
+    /// The most recently undone action last.
+    redo: Vec<Action>,
+}
+
+impl History {
+    /// Adds an action the player just took, the undone ones can't be redone anymore.
+    pub fn record(&mut self, action: Action) {
+        self.redo.clear();
+        self.push_undo(action);
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    fn push_undo(&mut self, action: Action) {
+        if self.undo.len() == HISTORY_LIMIT {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(action);
+    }
+}
+
+/// A command which has been applied, together with everything needed to revert it.
+#[derive(Debug, Clone)]
+pub struct Action {
+    /// The command as issued, which is applied again to redo it.
+    command: SimCommand,
+    revert: Revert,
+    /// The [`NextTrainId`] before the command, trains created by it get the same ids on redo.
+    first_train_id: TrainId,
+    /// The costs before the command, which are restored when reverting it.
+    costs: ConstructionCosts,
+}
+
+/// How to revert an [`Action`], worked out while applying it.
+#[derive(Debug, Clone)]
+enum Revert {
+    /// Demolishes the tracks which have been built.
+    RemoveTracks(Vec<Track>),
+    /// Builds the demolished tracks again, together with what was removed along with them.
+    RestoreTracks {
+        tracks: Vec<(Track, Option<f32>)>,
+        signals: Vec<Joint>,
+        switches: Vec<(Joint, TrackType)>,
+    },
+    /// Selects the previously selected track of a switch.
+    SelectTrack(Track),
+    ToggleSignal(Joint),
+    /// Sets the speed limits back, `None` being the default for the track.
+    SetSpeedLimits(Vec<(Track, Option<f32>)>),
+    DespawnTrain(TrainId),
+    RemoveLastVehicle(TrainId),
+    /// Splits the `back` train off the `front` one again, which had `length` vehicles.
+    /// `reversed` is the train which was turned around to couple them.
+    Uncouple {
+        front: TrainId,
+        length: u16,
+        back: TrainId,
+        reversed: Option<TrainId>,
+    },
+    /// Couples the split off `back` train to the end of the `front` one again.
+    Couple {
+        front: TrainId,
+        back: TrainId,
+    },
+}
+
+impl Action {
+    /// Applies `command` to the world, returning the action if it changed anything
+    /// which can be undone.
+    pub fn apply(command: SimCommand, world: &mut World) -> Option<Action> {
+        let first_train_id = TrainId(world.resource::<NextTrainId>().0);
+        let costs = *world.resource::<ConstructionCosts>();
+        let revert = apply_revertible(&command, world, first_train_id)?;
+        Some(Action {
+            command,
+            revert,
+            first_train_id,
+            costs,
+        })
+    }
+
+    /// Reverts the changes of the action, restoring the costs from before it.
+    ///
+    /// Returns false and changes nothing if the world doesn't allow it anymore,
+    /// e.g. if a train stands on the tracks which were built.
+    pub fn revert(&self, world: &mut World) -> bool {
+        let result = match &self.revert {
+            Revert::RemoveTracks(tracks) => {
+                let is_occupied = world
+                    .query::<&Trail>()
+                    .iter(world)
+                    .any(|trail| tracks.iter().any(|&track| trail.covers_track(track)));
+                if is_occupied {
+                    warn!("Cannot remove the tracks, a train is standing on them");
+                    return false;
+                }
+                world
+                    .run_system_once_with(demolish_exact_tracks, tracks.clone())
+                    .map(|()| true)
+            }
+            Revert::RestoreTracks {
+                tracks,
+                signals,
+                switches,
+            } => {
+                SimCommand::LayTracks(tracks.iter().map(|&(track, _)| track).collect())
+                    .execute(world);
+                let mut rail_graph = world.resource_mut::<RailGraph>();
+                for &(track, speed_limit) in tracks {
+                    rail_graph.set_speed_limit(track, speed_limit);
+                }
+                rail_graph.signals.extend(signals.iter().copied());
+                rail_graph.switches.extend(switches.iter().copied());
+                Ok(true)
+            }
+            Revert::SelectTrack(track) => Ok(world
+                .resource_mut::<RailGraph>()
+                .set_switch(track.joint, track.heading)),
+            Revert::ToggleSignal(joint) => {
+                world.resource_mut::<RailGraph>().toggle_signal(*joint);
+                Ok(true)
+            }
+            Revert::SetSpeedLimits(speed_limits) => {
+                let mut rail_graph = world.resource_mut::<RailGraph>();
+                for &(track, speed_limit) in speed_limits {
+                    rail_graph.set_speed_limit(track, speed_limit);
+                }
+                Ok(true)
+            }
+            Revert::DespawnTrain(train) => {
+                let train = find_train(world, *train);
+                if let Some(train) = train {
+                    // Also despawns all the vehicles.
+                    world.entity_mut(train).despawn();
+                }
+                Ok(train.is_some())
+            }
+            Revert::RemoveLastVehicle(train) => {
+                let train = find_train(world, *train);
+                Ok(train.is_some_and(|train| remove_last_vehicle(world, train)))
+            }
+            &Revert::Uncouple {
+                front,
+                length,
+                back,
+                reversed,
+            } => revert_coupling(world, front, length, back, reversed),
+            &Revert::Couple { front, back } => {
+                let length = train_length(world, front);
+                SimCommand::Couple {
+                    first: front,
+                    first_end: BumperNode::Back,
+                    second: back,
+                    second_end: BumperNode::Front,
+                }
+                .execute(world);
+                Ok(length.is_some_and(|length| {
+                    train_length(world, front).is_some_and(|coupled| coupled > length)
+                }))
+            }
+        };
+        let reverted = result.unwrap_or_else(|e| {
+            error!("Reverting {:?} failed: {e:?}", self.command);
+            false
+        });
+        if reverted {
+            *world.resource_mut::<ConstructionCosts>() = self.costs;
+        }
+        reverted
+    }
+}
+
+/// Applies `command` and works out how to revert it,
+/// `None` if it can't be undone or didn't change anything.
+fn apply_revertible(
+    command: &SimCommand,
+    world: &mut World,
+    first_train_id: TrainId,
+) -> Option<Revert> {
+    let execute = |world: &mut World| command.clone().execute(world);
+    match *command {
+        SimCommand::LayTrack(track) => {
+            let rail_graph = world.resource::<RailGraph>();
+            let existed = rail_graph.contains_track(track);
+            let selected = rail_graph.route_from(track.joint);
+            execute(world);
+            let rail_graph = world.resource::<RailGraph>();
+            if !existed {
+                return rail_graph
+                    .contains_track(track)
+                    .then(|| Revert::RemoveTracks(vec![track]));
+            }
+            // Laying an existing track selects it at a switch
+            selected
+                .filter(|&selected| selected != track)
+                .filter(|_| rail_graph.route_from(track.joint) == Some(track))
+                .map(Revert::SelectTrack)
+        }
+        SimCommand::LayTracks(ref tracks) => {
+            let rail_graph = world.resource::<RailGraph>();
+            let new_tracks: Vec<Track> = tracks
+                .iter()
+                .copied()
+                .filter(|&track| !rail_graph.contains_track(track))
+                .collect();
+            execute(world);
+            let rail_graph = world.resource::<RailGraph>();
+            let built: Vec<Track> = new_tracks
+                .into_iter()
+                .filter(|&track| rail_graph.contains_track(track))
+                .collect();
+            (!built.is_empty()).then_some(Revert::RemoveTracks(built))
+        }
+        SimCommand::Demolish(joint) => {
+            let rail_graph = world.resource::<RailGraph>();
+            let tracks: Vec<(Track, Option<f32>)> = rail_graph
+                .tracks_from(joint)
+                .into_iter()
+                .map(|track| (track, speed_limit_setting(rail_graph, track)))
+                .collect();
+            let signals = rail_graph.signals.clone();
+            let switches = rail_graph.switches.clone();
+            execute(world);
+            let rail_graph = world.resource::<RailGraph>();
+            let removed: Vec<(Track, Option<f32>)> = tracks
+                .into_iter()
+                .filter(|&(track, _)| !rail_graph.contains_track(track))
+                .collect();
+            if removed.is_empty() {
+                return None;
+            }
+            Some(Revert::RestoreTracks {
+                tracks: removed,
+                signals: signals.difference(&rail_graph.signals).copied().collect(),
+                switches: switches
+                    .into_iter()
+                    .filter(|(joint, heading)| rail_graph.switches.get(joint) != Some(heading))
+                    .collect(),
+            })
+        }
+        SimCommand::ToggleSignal(joint) => {
+            let had_signal = world.resource::<RailGraph>().signals.contains(&joint);
+            execute(world);
+            let has_signal = world.resource::<RailGraph>().signals.contains(&joint);
+            (had_signal != has_signal).then_some(Revert::ToggleSignal(joint))
+        }
+        SimCommand::CycleSpeedLimit(joint) => {
+            let rail_graph = world.resource::<RailGraph>();
+            let speed_limits: Vec<(Track, Option<f32>)> = rail_graph
+                .tracks_from(joint)
+                .into_iter()
+                .map(|track| (track, speed_limit_setting(rail_graph, track)))
+                .collect();
+            execute(world);
+            (!speed_limits.is_empty()).then_some(Revert::SetSpeedLimits(speed_limits))
+        }
+        SimCommand::SpawnTrain { .. } => {
+            execute(world);
+            let spawned = world.resource::<NextTrainId>().0 > first_train_id.0;
+            spawned.then_some(Revert::DespawnTrain(first_train_id))
+        }
+        SimCommand::AppendVehicle { train, .. } => {
+            let length_before = train_length(world, train)?;
+            execute(world);
+            let appended = train_length(world, train).is_some_and(|length| length > length_before);
+            appended.then_some(Revert::RemoveLastVehicle(train))
+        }
+        SimCommand::Couple {
+            first,
+            first_end,
+            second,
+            second_end,
+        } => {
+            // The same cases as in `couple_trains`
+            let (front, back, reversed) = match (first_end, second_end) {
+                (BumperNode::Front, BumperNode::Back) => (second, first, None),
+                (BumperNode::Back, BumperNode::Front) => (first, second, None),
+                (BumperNode::Front, BumperNode::Front) => (first, second, Some(first)),
+                (BumperNode::Back, BumperNode::Back) => (first, second, Some(second)),
+            };
+            let length = train_length(world, front)?;
+            execute(world);
+            // The back train is merged into the front one
+            let coupled = train_length(world, front).is_some_and(|l| l > length);
+            coupled.then_some(Revert::Uncouple {
+                front,
+                length,
+                back,
+                reversed,
+            })
+        }
+        SimCommand::Uncouple { train, .. } => {
+            execute(world);
+            let uncoupled = world.resource::<NextTrainId>().0 > first_train_id.0;
+            uncoupled.then_some(Revert::Couple {
+                front: train,
+                back: first_train_id,
+            })
+        }
+        SimCommand::DespawnTrain(_)
+        | SimCommand::RemoveVehicle { .. }
+        | SimCommand::Rerail(_)
+        | SimCommand::SetController { .. }
+        | SimCommand::Steer { .. }
+        | SimCommand::Reverse(_)
+        | SimCommand::SetDestination { .. }
+        | SimCommand::AppendOrders { .. }
+        | SimCommand::ClearOrders(_)
+        | SimCommand::ToggleStation(_)
+        | SimCommand::SetSpeedLimitMode(_)
+        | SimCommand::Undo
+        | SimCommand::Redo => {
+            execute(world);
+            None
+        }
+    }
+}
+
+/// The speed limit set for `track`, `None` if it has the default one.
+fn speed_limit_setting(rail_graph: &RailGraph, track: Track) -> Option<f32> {
+    rail_graph
+        .graph
+        .edge_weight(track.joint, track.end_joint())
+        .and_then(|properties| properties.speed_limit)
+}
+
+/// Returns the number of vehicles of the train, without warning if it doesn't exist.
+fn train_length(world: &mut World, id: TrainId) -> Option<u16> {
+    world
+        .query::<(&TrainId, &Trail)>()
+        .iter(world)
+        .find(|&(&train_id, _)| train_id == id)
+        .map(|(_, trail)| trail.length)
+}
+
+/// Runs `f` such that the trains it creates get the ids from `first` on,
+/// which must not be used by any train.
+fn with_train_ids<R>(world: &mut World, first: TrainId, f: impl FnOnce(&mut World) -> R) -> R {
+    let next = world.resource::<NextTrainId>().0;
+    world.resource_mut::<NextTrainId>().0 = first.0;
+    let result = f(world);
+    let mut next_id = world.resource_mut::<NextTrainId>();
+    next_id.0 = next_id.0.max(next);
+    result
+}
+
+/// Despawns the last vehicle of `train`, unless it is the only one.
+///
+/// Returns true if a vehicle was removed.
+fn remove_last_vehicle(world: &mut World, train: Entity) -> bool {
+    let Some(length) = world.get::<Trail>(train).map(|trail| trail.length) else {
+        return false;
+    };
+    if length <= 1 {
+        warn!("Cannot remove the only vehicle of train {train:?}");
+        return false;
+    }
+    let last = world
+        .query::<(Entity, &VehicleOf, &TrainIndex)>()
+        .iter(world)
+        .find(|&(_, vehicle_of, index)| vehicle_of.train() == train && index.position == length - 1)
+        .map(|(vehicle, _, _)| vehicle);
+    let Some(last) = last else {
+        error!("Train {train:?} has no vehicle at index {}", length - 1);
+        return false;
+    };
+    // Also despawns the bumpers.
+    world.entity_mut(last).despawn();
+    if let Some(mut trail) = world.get_mut::<Trail>(train) {
+        trail.length -= 1;
+    }
+    true
+}
+
+/// Splits the `back` train off `front` again and turns the `reversed` one back around.
+///
+/// Returns true if the trains were split.
+fn revert_coupling(
+    world: &mut World,
+    front: TrainId,
+    length: u16,
+    back: TrainId,
+    reversed: Option<TrainId>,
+) -> Result<bool, RunSystemError> {
+    let Some(front_entity) = find_train(world, front) else {
+        return Ok(false);
+    };
+    with_train_ids(world, back, |world| {
+        world.run_system_once_with(uncouple, (front_entity, length))
+    })?;
+    if train_length(world, front) != Some(length) {
+        return Ok(false);
+    }
+    let Some(reversed) = reversed else {
+        return Ok(true);
+    };
+    let Some(reversed) = find_train(world, reversed) else {
+        return Ok(true);
+    };
+    world
+        .run_system_once_with(reverse_train, reversed)
+        .map(|()| true)
+}
+
+/// Reverts the last action.
+pub fn undo(world: &mut World) {
+    let Some(action) = world.resource_mut::<History>().undo.pop_back() else {
+        info!("Nothing to undo");
+        return;
+    };
+    debug!("Undoing {:?}", action.command);
+    if action.revert(world) {
+        world.resource_mut::<History>().redo.push(action);
+    } else {
+        warn!("Cannot undo {:?}", action.command);
+        // Maybe it can be undone later, e.g. once the train has left the tracks
+        world.resource_mut::<History>().push_undo(action);
+    }
+}
+
+/// Applies the last undone action again.
+pub fn redo(world: &mut World) {
+    let Some(action) = world.resource_mut::<History>().redo.pop() else {
+        info!("Nothing to redo");
+        return;
+    };
+    debug!("Redoing {:?}", action.command);
+    // The ids are free again, since the trains were removed by undoing the action
+    let redone = with_train_ids(world, action.first_train_id, |world| {
+        Action::apply(action.command, world)
+    });
+    match redone {
+        Some(redone) => world.resource_mut::<History>().push_undo(redone),
+        None => warn!("Redoing changed nothing"),
+    }
+}
+
+// ================================ SYSTEMS ===================================
+
+fn undo_redo_system(action: Single<&MenuInput>, mut sim_commands: MessageWriter<SimCommand>) {
+    if action.just_pressed(&MenuAction::Undo) {
+        sim_commands.write(SimCommand::Undo);
+    }
+    if action.just_pressed(&MenuAction::Redo) {
+        sim_commands.write(SimCommand::Redo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::headless_app;
+
+    /// A straight track from each of the first `count` joints of a line from the origin.
+    fn straight_tracks(count: usize) -> Vec<Track> {
+        iter::successors(Some(Joint::default()), |joint| Some(joint.next_straight()))
+            .take(count)
+            .map(|joint| Track {
+                joint,
+                heading: TrackType::Straight,
+            })
+            .collect()
+    }
+
+    /// Applies `command` in the next fixed step.
+    fn run(app: &mut App, command: SimCommand) {
+        app.world_mut().write_message(command);
+        app.update();
+    }
+
+    /// A headless app with the tracks of [`straight_tracks`] built.
+    fn app_with_tracks(count: usize) -> App {
+        let mut app = headless_app();
+        app.update();
+        run(&mut app, SimCommand::LayTracks(straight_tracks(count)));
+        app
+    }
+
+    fn spawn(app: &mut App, track: Track) {
+        let command = SimCommand::SpawnTrain {
+            joint: track.joint,
+            vehicle_type: VehicleType::Locomotive,
+        };
+        run(app, command);
+    }
+
+    fn has_track(app: &App, track: Track) -> bool {
+        app.world().resource::<RailGraph>().contains_track(track)
+    }
+
+    fn costs(app: &App) -> u64 {
+        app.world().resource::<ConstructionCosts>().0
+    }
+
+    /// The ids and lengths of all trains.
+    fn trains(app: &mut App) -> Vec<(TrainId, u16)> {
+        let world = app.world_mut();
+        let mut trains = world
+            .query::<(&TrainId, &Trail)>()
+            .iter(world)
+            .map(|(&id, trail)| (id, trail.length))
+            .collect::<Vec<_>>();
+        trains.sort_by_key(|&(id, _)| id.0);
+        trains
+    }
+
+    /// Two single vehicle trains, the second one just behind the first one.
+    fn app_with_two_trains() -> App {
+        let tracks = straight_tracks(3);
+        let mut app = app_with_tracks(3);
+        spawn(&mut app, tracks[1]);
+        spawn(&mut app, tracks[0]);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1), (TrainId(2), 1)]);
+        app
+    }
+
+    fn couple(app: &mut App) {
+        let command = SimCommand::Couple {
+            first: TrainId(1),
+            first_end: BumperNode::Back,
+            second: TrainId(2),
+            second_end: BumperNode::Front,
+        };
+        run(app, command);
+    }
+
+    #[test]
+    fn undo_and_redo_laying_tracks() {
+        let tracks = straight_tracks(3);
+        let mut app = app_with_tracks(3);
+        assert!(tracks.iter().all(|&track| has_track(&app, track)));
+        let built_costs = costs(&app);
+        assert!(built_costs > 0);
+
+        run(&mut app, SimCommand::Undo);
+        assert!(!tracks.iter().any(|&track| has_track(&app, track)));
+        assert_eq!(costs(&app), 0);
+
+        run(&mut app, SimCommand::Redo);
+        assert!(tracks.iter().all(|&track| has_track(&app, track)));
+        assert_eq!(costs(&app), built_costs);
+    }
+
+    #[test]
+    fn undo_and_redo_demolishing() {
+        let tracks = straight_tracks(3);
+        let mut app = app_with_tracks(3);
+        run(&mut app, SimCommand::ToggleSignal(tracks[1].joint));
+        run(&mut app, SimCommand::Demolish(tracks[1].joint));
+        assert!(!has_track(&app, tracks[1]));
+        assert!(has_track(&app, tracks[0]) && has_track(&app, tracks[2]));
+
+        run(&mut app, SimCommand::Undo);
+        assert!(has_track(&app, tracks[1]));
+        let rail_graph = app.world().resource::<RailGraph>();
+        assert!(rail_graph.signals.contains(&tracks[1].joint));
+
+        run(&mut app, SimCommand::Redo);
+        assert!(!has_track(&app, tracks[1]));
+    }
+
+    #[test]
+    fn undo_and_redo_spawning() {
+        let tracks = straight_tracks(3);
+        let mut app = app_with_tracks(3);
+        spawn(&mut app, tracks[0]);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1)]);
+
+        run(&mut app, SimCommand::Undo);
+        assert!(trains(&mut app).is_empty());
+
+        // The train gets the same id again
+        run(&mut app, SimCommand::Redo);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1)]);
+    }
+
+    #[test]
+    fn undo_and_redo_coupling() {
+        let mut app = app_with_two_trains();
+        couple(&mut app);
+        assert_eq!(trains(&mut app), [(TrainId(1), 2)]);
+
+        run(&mut app, SimCommand::Undo);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1), (TrainId(2), 1)]);
+
+        run(&mut app, SimCommand::Redo);
+        assert_eq!(trains(&mut app), [(TrainId(1), 2)]);
+    }
+
+    #[test]
+    fn undo_and_redo_uncoupling() {
+        let mut app = app_with_two_trains();
+        couple(&mut app);
+        let uncouple = SimCommand::Uncouple {
+            train: TrainId(1),
+            index: 1,
+        };
+        run(&mut app, uncouple);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1), (TrainId(3), 1)]);
+
+        run(&mut app, SimCommand::Undo);
+        assert_eq!(trains(&mut app), [(TrainId(1), 2)]);
+
+        run(&mut app, SimCommand::Redo);
+        assert_eq!(trains(&mut app), [(TrainId(1), 1), (TrainId(3), 1)]);
+    }
+
+    #[test]
+    fn occupied_tracks_are_not_removed_by_undo() {
+        let tracks = straight_tracks(12);
+        let mut app = app_with_tracks(2);
+        spawn(&mut app, tracks[0]);
+        run(&mut app, SimCommand::LayTracks(tracks[2..].to_vec()));
+        let built_costs = costs(&app);
+        let controller = Controller {
+            throttle: 1.0,
+            brake: 0.0,
+        };
+        run(
+            &mut app,
+            SimCommand::SetController {
+                train: TrainId(1),
+                controller,
+            },
+        );
+        // About five seconds, until the train is on the tracks built last
+        for _ in 0..320 {
+            app.update();
+        }
+
+        run(&mut app, SimCommand::Undo);
+        assert!(tracks.iter().all(|&track| has_track(&app, track)));
+        assert_eq!(costs(&app), built_costs);
+        let history = app.world().resource::<History>();
+        assert_eq!((history.undo_len(), history.redo_len()), (3, 0));
+    }
+}
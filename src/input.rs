@@ -15,6 +15,7 @@ impl Plugin for InputPlugin {
         BuildAction::init(app);
         DriveAction::init(app);
         CameraAction::init(app);
+        SaveMenuAction::init(app);
     }
 }
 
@@ -90,10 +91,17 @@ pub enum MenuAction {
     Drive,
     BuildTracks,
     SpawnVehicles,
+    SaveMenu,
     // Savegame
     Reload,
     NewGame,
     Save,
+    ToggleRecording,
+    // History
+    Undo,
+    Redo,
+    // Settings
+    ToggleDerailing,
     // Debug
     Help,
     ToggleGizmos,
@@ -105,6 +113,7 @@ pub enum MenuState {
     Driving,
     Building,
     Spawning,
+    SaveMenu,
 }
 
 #[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
@@ -120,11 +129,22 @@ impl Subaction for MenuAction {
             .with(Self::Drive, KeyCode::Space)
             .with(Self::BuildTracks, KeyCode::KeyT)
             .with(Self::SpawnVehicles, KeyCode::KeyV)
+            .with(Self::SaveMenu, KeyCode::F8)
             .with(Self::Reload, KeyCode::F5)
             .with(Self::NewGame, KeyCode::F7)
             .with(Self::Save, KeyCode::F6)
+            .with(Self::ToggleRecording, KeyCode::F9)
+            .with(
+                Self::Undo,
+                ButtonlikeChord::modified(ModifierKey::Control, KeyCode::KeyZ),
+            )
+            .with(
+                Self::Redo,
+                ButtonlikeChord::modified(ModifierKey::Control, KeyCode::KeyY),
+            )
             .with(Self::Help, KeyCode::F1)
             .with(Self::ToggleGizmos, KeyCode::F2)
+            .with(Self::ToggleDerailing, KeyCode::F3)
     }
 
     fn additional_init(app: &mut App) {
@@ -136,6 +156,7 @@ impl Subaction for MenuAction {
                     MenuAction::Drive => MenuState::Driving,
                     MenuAction::BuildTracks => MenuState::Building,
                     MenuAction::SpawnVehicles => MenuState::Spawning,
+                    MenuAction::SaveMenu => MenuState::SaveMenu,
                 ),
             )
             .init_state::<DebugGizmosState>()
@@ -170,6 +191,9 @@ pub enum DriveAction {
     Reverse,
     #[actionlike(Axis)]
     SwitchDirection,
+    // Orders
+    AppendOrder,
+    ClearOrders,
 }
 
 impl Subaction for DriveAction {
@@ -182,6 +206,8 @@ impl Subaction for DriveAction {
             .with(Self::Brake, KeyCode::ArrowDown)
             .with(Self::Reverse, KeyCode::KeyR)
             .with_axis(Self::SwitchDirection, VirtualAxis::horizontal_arrow_keys())
+            .with(Self::AppendOrder, KeyCode::ShiftLeft)
+            .with(Self::ClearOrders, KeyCode::Backspace)
     }
     fn additional_init(app: &mut App) {
         Self::toggle_with(app, MenuState::Driving)
@@ -199,11 +225,27 @@ pub enum BuildAction {
     SelectLeft,
     SelectStraight,
     SelectRight,
+    SelectDemolish,
+    SelectSignal,
+    SelectStation,
+    SelectSpeedLimit,
+    SelectCopyBlueprint,
+    SelectPasteBlueprint,
+    // Blueprints
+    RotateBlueprint,
+    MirrorBlueprint,
+    NextBlueprint,
 }
 
 #[derive(States, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum BuildingState {
     LayTrack(TrackType),
+    Demolish,
+    PlaceSignal,
+    PlaceStation,
+    SetSpeedLimit,
+    CopyBlueprint,
+    PasteBlueprint,
 }
 
 impl Default for BuildingState {
@@ -219,6 +261,15 @@ impl Subaction for BuildAction {
             .with(Self::SelectLeft, KeyCode::Digit1)
             .with(Self::SelectStraight, KeyCode::Digit2)
             .with(Self::SelectRight, KeyCode::Digit3)
+            .with(Self::SelectDemolish, KeyCode::Digit4)
+            .with(Self::SelectSignal, KeyCode::Digit5)
+            .with(Self::SelectStation, KeyCode::Digit6)
+            .with(Self::SelectSpeedLimit, KeyCode::Digit7)
+            .with(Self::SelectCopyBlueprint, KeyCode::Digit8)
+            .with(Self::SelectPasteBlueprint, KeyCode::Digit9)
+            .with(Self::RotateBlueprint, KeyCode::KeyR)
+            .with(Self::MirrorBlueprint, KeyCode::KeyM)
+            .with(Self::NextBlueprint, KeyCode::KeyB)
     }
 
     fn additional_init(app: &mut App) {
@@ -229,6 +280,12 @@ impl Subaction for BuildAction {
                 BuildAction::SelectLeft => BuildingState::LayTrack(TrackType::CurvedLeft),
                 BuildAction::SelectStraight => BuildingState::LayTrack(TrackType::Straight),
                 BuildAction::SelectRight => BuildingState::LayTrack(TrackType::CurvedRight),
+                BuildAction::SelectDemolish => BuildingState::Demolish,
+                BuildAction::SelectSignal => BuildingState::PlaceSignal,
+                BuildAction::SelectStation => BuildingState::PlaceStation,
+                BuildAction::SelectSpeedLimit => BuildingState::SetSpeedLimit,
+                BuildAction::SelectCopyBlueprint => BuildingState::CopyBlueprint,
+                BuildAction::SelectPasteBlueprint => BuildingState::PasteBlueprint,
             ),
         );
         Self::toggle_with(app, MenuState::Building);
@@ -287,3 +344,37 @@ impl Subaction for SpawnAction {
     }
 }
 // endregion -- Vehicle spawning
+
+// region -- Save menu
+pub type SaveMenuInput = ActionState<SaveMenuAction>;
+
+#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+pub enum SaveMenuAction {
+    Previous,
+    Next,
+    Load,
+    Overwrite,
+    SaveAs,
+    Delete,
+    Convert,
+    Close,
+}
+
+impl Subaction for SaveMenuAction {
+    fn make_input_map() -> InputMap<Self> {
+        InputMap::default()
+            .with(Self::Previous, KeyCode::ArrowUp)
+            .with(Self::Next, KeyCode::ArrowDown)
+            .with(Self::Load, KeyCode::Enter)
+            .with(Self::Overwrite, KeyCode::KeyO)
+            .with(Self::SaveAs, KeyCode::KeyN)
+            .with(Self::Delete, KeyCode::Delete)
+            .with(Self::Convert, KeyCode::KeyC)
+            .with(Self::Close, KeyCode::Escape)
+    }
+
+    fn additional_init(app: &mut App) {
+        Self::toggle_with(app, MenuState::SaveMenu);
+    }
+}
+// endregion -- Save menu
@@ -26,6 +26,7 @@ pub struct InteractPlugin;
 impl Plugin for InteractPlugin {
     fn build(&self, app: &mut App) {
         app.add_message::<TileClickEvent>()
+            .init_resource::<CursorWorldPos>()
             .add_observer(emit_train_events)
             // Ordering the event to be after the input, but still in PreUpdate,
             // so in Update the events are available
@@ -45,6 +46,12 @@ impl Plugin for InteractPlugin {
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct InteractSet;
 
+/// The position of the cursor in the world, `None` if it is outside the window or above the UI.
+///
+/// Updated in `PreUpdate` together with the [`InteractionStatus`].
+#[derive(Resource, Debug, Default)]
+pub struct CursorWorldPos(pub Option<Vec2>);
+
 /// A circle collider in the world that can be interacted with.
 ///
 /// Also needs an [`InteractionStatus`] component.
@@ -181,8 +188,10 @@ fn update_interaction_status(
         &mut InteractionStatus,
     )>,
     mut world_interaction: WorldInteractionQuery,
+    mut cursor: ResMut<CursorWorldPos>,
 ) {
     let cursor_pos = world_interaction.get_cursor_world_pos();
+    cursor.0 = cursor_pos;
 
     let mut nearest = None;
     for (id, position, node, mut status) in nodes.iter_mut() {
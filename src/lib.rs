@@ -0,0 +1,257 @@
+//! Hex Rails, a railroad game on a hexagonal grid.
+//!
+//! The game itself is the `hexrails` binary, this library also lets other tools like
+//! `hexrails-save` use the same types.
+//!
+//! The plugins are split in two groups: the [`SimulationPlugins`] move the trains and need
+//! neither a window nor any assets, while the [`GamePlugins`] add input, sprites and savegames
+//! on top of them. See [`headless_app`] to run only the simulation.
+//!
+//! The simulation runs entirely in the fixed schedules and the player only changes it with
+//! [`SimCommand`](commands::SimCommand)s, so a session can be [replayed](replay).
+
+use bevy::{app::PluginGroupBuilder, mesh::Mesh, prelude::*, time::TimeUpdateStrategy};
+use bevy_rapier2d::plugin::{NoUserData, RapierPhysicsPlugin};
+
+pub mod autopilot;
+pub mod blueprints;
+pub mod camera;
+pub mod collisions;
+pub mod commands;
+pub mod construction;
+pub mod debug;
+pub mod driving;
+pub mod history;
+pub mod input;
+pub mod interact;
+pub mod orders;
+pub mod railroad;
+pub mod replay;
+pub mod routing;
+pub mod savegame;
+pub mod signals;
+pub mod sprites;
+pub mod stations;
+pub mod terrain;
+pub mod tilemap;
+pub mod trainbuilder;
+pub mod trains;
+// pub mod ui;
+
+pub const BG_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+pub const ASPECT_RATIO: f32 = 16.0 / 9.0;
+
+/// Everything needed to run the trains, without rendering, input or [`SpriteAssets`].
+///
+/// [`SpriteAssets`]: sprites::SpriteAssets
+pub struct SimulationPlugins;
+
+impl PluginGroup for SimulationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            // In the fixed schedule, such that the simulation is deterministic
+            .add(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).in_fixed_schedule())
+            .add(tilemap::TileMapPlugin)
+            .add(terrain::TerrainPlugin)
+            .add(replay::ReplayPlugin)
+            .add(commands::CommandsPlugin)
+            .add(history::HistoryPlugin)
+            .add(railroad::RailRoadPlugin)
+            .add(signals::SignalPlugin)
+            .add(trains::TrainPlugin)
+            .add(driving::DrivingPlugin)
+            .add(autopilot::AutopilotPlugin)
+            .add(orders::OrdersPlugin)
+            .add(collisions::CollisionPlugin)
+    }
+}
+
+/// The whole game, i.e. the [`SimulationPlugins`] plus everything the player interacts with.
+///
+/// Requires the `DefaultPlugins`.
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add_group(SimulationPlugins)
+            .add(sprites::AssetPlugin)
+            .add(blueprints::BlueprintPlugin)
+            .add(camera::MovingCameraPlugin)
+            .add(debug::DebugPlugin)
+            .add(history::HistoryUiPlugin)
+            .add(railroad::RailRoadUiPlugin)
+            .add(savegame::LoadSavePlugin)
+            .add(signals::SignalUiPlugin)
+            .add(stations::StationPlugin)
+            .add(terrain::TerrainUiPlugin)
+            .add(trainbuilder::TrainBuildingPlugin)
+            .add(trains::TrainUiPlugin)
+            // .add(ui::UIOverlayPlugin)
+            .add(input::InputPlugin)
+            .add(interact::InteractPlugin)
+            .add(driving::ManualDrivingPlugin)
+            .add(autopilot::AutopilotUiPlugin)
+            .add(orders::OrdersUiPlugin)
+    }
+}
+
+/// Creates an app running only the [`SimulationPlugins`], e.g. for tests and tools.
+///
+/// Every [`App::update`] advances the simulation by exactly one `FixedUpdate` step,
+/// independent of the real time passed.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        bevy::asset::AssetPlugin::default(),
+    ))
+    // Rapier reads meshes for colliders created from them, even though there are none here
+    .init_asset::<Mesh>()
+    .insert_resource(TimeUpdateStrategy::FixedTimesteps(1))
+    .add_plugins(SimulationPlugins);
+    app
+}
+
+#[macro_export]
+macro_rules! ok_or_tt {
+    ($ttt: tt, $x:expr, $err:literal) => {{
+        match $x {
+            Ok(x) => x,
+            Err(e) => {
+                error!(concat!($err, ": {err:?}"), err = e);
+                $ttt;
+            }
+        }
+    }};
+    ($ttt: tt, $x:expr) => {{
+        let Ok(x) = $x else {
+            $ttt;
+        };
+        x
+    }};
+}
+
+#[macro_export]
+macro_rules! some_or_tt {
+    ($ttt: tt, $x:expr, $err:literal) => {{
+        match $x {
+            Some(x) => x,
+            None => {
+                error!($err);
+                $ttt;
+            }
+        }
+    }};
+    ($ttt: tt, $x:expr) => {{
+        let Some(x) = $x else {
+            $ttt;
+        };
+        x
+    }};
+}
+
+#[macro_export]
+macro_rules! ok_or_return {
+    ($x:expr, $err:literal) => {
+        crate::ok_or_tt!(return, $x, $err)
+    };
+    ($x:expr) => {
+        crate::ok_or_tt!(return, $x)
+    };
+}
+
+#[macro_export]
+macro_rules! some_or_return {
+    ($x:expr, $err:literal) => {
+        crate::some_or_tt!(return, $x, $err)
+    };
+    ($x:expr) => {
+        crate::some_or_tt!(return, $x)
+    };
+}
+
+#[macro_export]
+macro_rules! ok_or_continue {
+    ($x:expr, $err:literal) => {
+        crate::ok_or_tt!(continue, $x, $err)
+    };
+    ($x:expr) => {
+        crate::ok_or_tt!(continue, $x)
+    };
+}
+
+#[macro_export]
+macro_rules! some_or_continue {
+    ($x:expr, $err:literal) => {
+        crate::some_or_tt!(continue, $x, $err)
+    };
+    ($x:expr) => {
+        crate::some_or_tt!(continue, $x)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, iter, process};
+
+    use super::*;
+    use crate::commands::SimCommand;
+    use crate::railroad::{Track, TrackType};
+    use crate::savegame::{self, SaveFormat};
+    use crate::tilemap::Joint;
+    use crate::trains::{Controller, Trail, TrainId, VehicleType};
+
+    /// The joints of a straight line, starting at the origin.
+    fn straight_line() -> impl Iterator<Item = Joint> {
+        iter::successors(Some(Joint::default()), |joint| Some(joint.next_straight()))
+    }
+
+    fn front_joint(app: &mut App) -> Joint {
+        let mut trails = app.world_mut().query::<&Trail>();
+        let trail = trails.single(app.world()).unwrap();
+        trail.path[trail.path_progress.floor() as usize]
+    }
+
+    #[test]
+    fn headless_app_drives_loaded_train() {
+        let path = env::temp_dir().join(format!("hexrails-headless-{}.json", process::id()));
+        let tracks = straight_line()
+            .take(20)
+            .map(|joint| Track {
+                joint,
+                heading: TrackType::Straight,
+            })
+            .collect();
+        let mut app = headless_app();
+        app.world_mut().write_message(SimCommand::LayTracks(tracks));
+        app.world_mut().write_message(SimCommand::SpawnTrain {
+            joint: Joint::default(),
+            vehicle_type: VehicleType::Locomotive,
+        });
+        app.update();
+        savegame::save_file(app.world_mut(), &path, SaveFormat::Json).unwrap();
+
+        let mut app = headless_app();
+        app.update();
+        savegame::load_file(app.world_mut(), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let start = front_joint(&mut app);
+        app.world_mut().write_message(SimCommand::SetController {
+            train: TrainId(1),
+            controller: Controller {
+                throttle: 1.0,
+                brake: 0.0,
+            },
+        });
+        // About four seconds, enough for a few tracks
+        for _ in 0..256 {
+            app.update();
+        }
+
+        let front = front_joint(&mut app);
+        let position = |joint| straight_line().take(20).position(|other| other == joint);
+        assert!(position(front).unwrap() > position(start).unwrap() + 1);
+    }
+}
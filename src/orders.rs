@@ -0,0 +1,530 @@
+//! This module implements order lists for trains, which let trains run on their own.
+//!
+//! Every train has an [`Orders`] component, with a list of stops to go to and waits in between.
+//! A [`Order::GoTo`] hands the train to the [`Autopilot`], which drives it to the stop.
+//! Once the order list is done, it either starts over from the beginning or the train stays
+//! where it is. If none of the stops can be reached for a whole round, the train stays too.
+//!
+//! In driving mode, holding shift and clicking a track appends a stop to the orders of the
+//! selected train, and backspace clears them.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    autopilot::{has_arrived, route_start, Autopilot},
+    commands::SimCommand,
+    input::{DriveAction, DriveInput, MenuState},
+    interact::TileClickEvent,
+    railroad::RailGraph,
+    stations::Station,
+    tilemap::Joint,
+    trains::*,
+};
+
+/// How long trains wait at a station, when the stop was added by clicking on it.
+const DEFAULT_DWELL_TIME: f32 = 10.0;
+
+pub struct OrdersPlugin;
+
+impl Plugin for OrdersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            execute_orders
+                .in_set(ControlSet::Drive)
+                .before(crate::autopilot::drive_autopilot),
+        );
+    }
+}
+
+/// Editing the orders of the selected train.
+pub struct OrdersUiPlugin;
+
+impl Plugin for OrdersUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            edit_orders_system.run_if(in_state(MenuState::Driving)),
+        );
+    }
+}
+
+/// A place a train can be sent to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Stop {
+    /// Stop with the front of the train at this joint.
+    Joint(Joint),
+    /// Stop at the far end of the platform of the station with this name.
+    Station(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Order {
+    /// Drive to `stop`.
+    ///
+    /// `arrival` is the scheduled arrival in seconds since the start of the order list.
+    /// If there is none and the orders repeat, the first arrival is taken as the schedule.
+    GoTo {
+        stop: Stop,
+        #[serde(default)]
+        arrival: Option<f32>,
+    },
+    /// Stand still for this many seconds.
+    ///
+    /// There is no cargo yet, so there is no waiting until loaded.
+    Wait { seconds: f32 },
+}
+
+/// The orders of a train, which are executed by [`execute_orders`] one after another.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Orders {
+    pub orders: Vec<Order>,
+    /// The index of the order being executed. Equal to `orders.len()` if all are done.
+    pub current: usize,
+    /// Start over with the first order after the last one.
+    pub repeat: bool,
+    /// Seconds since the order list (re)started, this is what the schedule refers to.
+    pub elapsed: f32,
+    /// Seconds since the current order started.
+    pub order_elapsed: f32,
+    /// How many seconds the train was late at its last scheduled stop, negative if early.
+    pub delay: Option<f32>,
+    /// The destination handed to the [`Autopilot`] for the current order, if any.
+    #[serde(default)]
+    dispatched: Option<Joint>,
+    /// How many stops in a row couldn't be reached.
+    #[serde(default)]
+    unreachable: usize,
+}
+
+impl Orders {
+    pub fn current_order(&self) -> Option<&Order> {
+        self.orders.get(self.current)
+    }
+
+    /// Continues with the next order, starting over if the orders repeat.
+    fn advance(&mut self) {
+        self.current += 1;
+        self.order_elapsed = 0.0;
+        self.dispatched = None;
+        if self.current >= self.orders.len() && self.repeat {
+            self.current = 0;
+            self.elapsed = 0.0;
+        }
+    }
+
+    /// Skips the current order, since its stop can't be reached.
+    ///
+    /// Once none of the stops could be reached for a whole round, the orders are done
+    /// and don't repeat anymore, in which case this returns true.
+    fn skip_unreachable(&mut self) -> bool {
+        self.unreachable += 1;
+        let stops = self
+            .orders
+            .iter()
+            .filter(|order| matches!(order, Order::GoTo { .. }))
+            .count();
+        if self.unreachable >= stops {
+            self.repeat = false;
+            self.current = self.orders.len();
+            self.order_elapsed = 0.0;
+            self.dispatched = None;
+            true
+        } else {
+            self.advance();
+            false
+        }
+    }
+
+    /// Appends `orders`, which are executed next if all orders were done.
+    pub fn append(&mut self, orders: Vec<Order>, repeat: bool) {
+        self.orders.extend(orders);
+        self.repeat = repeat;
+        self.unreachable = 0;
+    }
+
+    /// Removes all orders, any started drive is finished by the autopilot.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl fmt::Display for Orders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.current_order() {
+            Some(order) => write!(
+                f,
+                "order {}/{}: {order:?}",
+                self.current + 1,
+                self.orders.len()
+            )?,
+            None => write!(f, "no orders")?,
+        }
+        match self.delay {
+            Some(delay) if delay >= 0.0 => write!(f, ", {delay:.1}s late"),
+            Some(delay) => write!(f, ", {:.1}s early", -delay),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Finds the joint a train starting at `start` should drive to, to stop at `stop`.
+///
+/// For stations, this is the far end of the platform, driving through from the end
+/// reached first from `start`. The length of the train isn't considered.
+/// Returns `None` if there is no route from `start` to the stop.
+fn resolve_stop<'a>(
+    stop: &Stop,
+    start: Joint,
+    rail_graph: &RailGraph,
+    mut stations: impl Iterator<Item = &'a Station>,
+) -> Option<Joint> {
+    let station = match stop {
+        Stop::Joint(joint) => {
+            // Like the autopilot, arriving from either direction
+            let is_reachable = [*joint, joint.opposite()]
+                .into_iter()
+                .any(|goal| rail_graph.find_route(start, goal).is_some());
+            return is_reachable.then_some(*joint);
+        }
+        Stop::Station(name) => stations.find(|station| &station.name == name)?,
+    };
+
+    let entry = station
+        .joints
+        .iter()
+        .flat_map(|&joint| [joint, joint.opposite()])
+        .filter_map(|goal| rail_graph.find_route(start, goal))
+        .min_by_key(|route| route.len())?
+        .last()
+        .copied()?;
+
+    // Drive through to the end of the platform
+    let mut joint = entry;
+    for _ in 0..station.joints.len() {
+        match rail_graph.route_from(joint) {
+            Some(track) if station.contains(track.end_joint()) => joint = track.end_joint(),
+            _ => break,
+        }
+    }
+    Some(joint)
+}
+
+// ================================ SYSTEMS ===================================
+
+/// System to execute the current order of every train, handing [`Order::GoTo`]s to the
+/// [`Autopilot`].
+///
+/// If the player takes over, the train is sent to its stop again once it stands still.
+fn execute_orders(
+    mut commands: Commands,
+    time: Res<Time>,
+    rail_graph: Res<RailGraph>,
+    stations: Query<&Station>,
+    mut trains: Query<
+        (Entity, &mut Orders, &Trail, &Velocity, Option<&Autopilot>),
+        (With<TrainMarker>, Without<Crashed>),
+    >,
+) {
+    for (train, mut orders, trail, velocity, autopilot) in trains.iter_mut() {
+        if orders.current_order().is_none() {
+            continue;
+        }
+        orders.elapsed += time.delta_secs();
+        orders.order_elapsed += time.delta_secs();
+
+        let current = orders.current;
+        match orders.orders[current].clone() {
+            Order::Wait { seconds } => {
+                if orders.order_elapsed >= seconds {
+                    orders.advance();
+                }
+            }
+            Order::GoTo { stop, arrival } => {
+                if autopilot.is_some() {
+                    // Either driving there, or the player sent the train elsewhere
+                    continue;
+                }
+
+                match orders.dispatched {
+                    Some(destination) if has_arrived(trail, destination) => {
+                        let elapsed = orders.elapsed;
+                        // Repeating orders learn their schedule in the first round
+                        let scheduled = arrival.or(orders.repeat.then_some(elapsed));
+                        if let Some(scheduled) = scheduled {
+                            orders.delay = Some(elapsed - scheduled);
+                            orders.orders[current] = Order::GoTo {
+                                stop: stop.clone(),
+                                arrival: Some(scheduled),
+                            };
+                        }
+                        info!("Train {train:?} arrived at {stop:?}, {}", *orders);
+                        orders.advance();
+                    }
+                    Some(_) if velocity.velocity > 0.0 => {
+                        // The player took over, wait for them to stop
+                    }
+                    _ => {
+                        let start = route_start(trail);
+                        let Some(destination) =
+                            resolve_stop(&stop, start, &rail_graph, stations.iter())
+                        else {
+                            warn!("Train {train:?} can't reach {stop:?}, skipping this order");
+                            if orders.skip_unreachable() {
+                                warn!("Train {train:?} can't reach any of its stops, stopping");
+                            }
+                            continue;
+                        };
+                        debug!("Train {train:?} is heading to {stop:?}");
+                        orders.dispatched = Some(destination);
+                        orders.unreachable = 0;
+                        commands.entity(train).insert(Autopilot { destination });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// System to append stops to the orders of the player controlled train,
+/// by clicking on tracks while holding [`DriveAction::AppendOrder`].
+fn edit_orders_system(
+    mut click_event: MessageReader<TileClickEvent>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    input: Single<&DriveInput>,
+    rail_graph: Res<RailGraph>,
+    stations: Query<&Station>,
+    trains: Query<&TrainId, With<PlayerControlledTrain>>,
+) {
+    if input.just_pressed(&DriveAction::ClearOrders) {
+        for &train in trains.iter() {
+            sim_commands.write(SimCommand::ClearOrders(train));
+        }
+    }
+    if !input.pressed(&DriveAction::AppendOrder) {
+        click_event.clear();
+        return;
+    }
+
+    for ev in click_event.read() {
+        let Some(side) = ev.side else {
+            continue;
+        };
+        let joint = Joint {
+            tile: ev.coord,
+            side,
+        };
+        if !rail_graph.graph.contains_node(joint) {
+            continue;
+        }
+
+        let orders = match stations.iter().find(|station| station.contains(joint)) {
+            Some(station) => vec![
+                Order::GoTo {
+                    stop: Stop::Station(station.name.clone()),
+                    arrival: None,
+                },
+                Order::Wait {
+                    seconds: DEFAULT_DWELL_TIME,
+                },
+            ],
+            None => vec![Order::GoTo {
+                stop: Stop::Joint(joint),
+                arrival: None,
+            }],
+        };
+        for &train in trains.iter() {
+            sim_commands.write(SimCommand::AppendOrders {
+                train,
+                orders: orders.clone(),
+                repeat: true,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::headless_app;
+    use crate::railroad::{Track, TrackType};
+    use crate::tilemap::{Direction, Tile};
+
+    fn go_to(joint: Joint, arrival: Option<f32>) -> Order {
+        Order::GoTo {
+            stop: Stop::Joint(joint),
+            arrival,
+        }
+    }
+
+    /// A headless app with a single locomotive at the start of a straight line of 8 tracks,
+    /// which gets `orders`. Returns the app and the end of the 5th track.
+    fn app_with_orders(orders: impl Fn(Joint) -> Vec<Order>, repeat: bool) -> (App, Joint) {
+        let tracks: Vec<_> =
+            iter::successors(Some(Joint::default()), |joint| Some(joint.next_straight()))
+                .take(8)
+                .map(|joint| Track {
+                    joint,
+                    heading: TrackType::Straight,
+                })
+                .collect();
+        let stop = tracks[4].end_joint();
+        let mut app = headless_app();
+        app.update();
+        for command in [
+            SimCommand::LayTracks(tracks.clone()),
+            SimCommand::SpawnTrain {
+                joint: tracks[0].joint,
+                vehicle_type: VehicleType::Locomotive,
+            },
+            SimCommand::AppendOrders {
+                train: TrainId(1),
+                orders: orders(stop),
+                repeat,
+            },
+        ] {
+            app.world_mut().write_message(command);
+            app.update();
+        }
+        (app, stop)
+    }
+
+    fn orders(app: &mut App) -> Orders {
+        let mut orders = app.world_mut().query::<&Orders>();
+        orders.single(app.world()).unwrap().clone()
+    }
+
+    /// Runs fixed steps until the train arrived at a scheduled stop, for at most a minute.
+    fn run_until_delay(app: &mut App) -> f32 {
+        for _ in 0..64 * 60 {
+            app.update();
+            if let Some(delay) = orders(app).delay {
+                return delay;
+            }
+        }
+        panic!("The train didn't arrive");
+    }
+
+    #[test]
+    fn orders_advance_and_repeat() {
+        let stop = Joint::default();
+        let mut orders = Orders::default();
+        orders.append(vec![go_to(stop, None), Order::Wait { seconds: 1.0 }], false);
+        assert_eq!(orders.current_order(), Some(&go_to(stop, None)));
+        orders.advance();
+        assert_eq!(orders.current_order(), Some(&Order::Wait { seconds: 1.0 }));
+        orders.advance();
+        assert_eq!(orders.current_order(), None);
+
+        orders.current = 0;
+        orders.repeat = true;
+        orders.elapsed = 5.0;
+        orders.advance();
+        orders.advance();
+        assert_eq!(orders.current, 0);
+        assert_eq!(orders.elapsed, 0.0);
+    }
+
+    #[test]
+    fn unreachable_orders_stop_after_a_round() {
+        let stop = Joint::default();
+        let mut orders = Orders::default();
+        let wait = Order::Wait { seconds: 1.0 };
+        orders.append(
+            vec![go_to(stop, None), wait.clone(), go_to(stop, None)],
+            true,
+        );
+        assert!(!orders.skip_unreachable());
+        assert_eq!(orders.current_order(), Some(&wait));
+        orders.advance();
+        assert!(orders.skip_unreachable());
+        assert_eq!(orders.current_order(), None);
+        assert!(!orders.repeat);
+
+        // New orders start over
+        orders.append(vec![go_to(stop, None)], true);
+        assert_eq!(orders.current, 3);
+        assert!(!orders.skip_unreachable());
+        assert_eq!(orders.current, 0);
+    }
+
+    #[test]
+    fn trains_wait_for_the_given_time() {
+        let (mut app, _) = app_with_orders(|_| vec![Order::Wait { seconds: 2.0 }], false);
+        // About one second
+        for _ in 0..64 {
+            app.update();
+        }
+        assert_eq!(orders(&mut app).current, 0);
+        // About two and a half seconds
+        for _ in 0..96 {
+            app.update();
+        }
+        assert_eq!(orders(&mut app).current_order(), None);
+    }
+
+    #[test]
+    fn delays_are_reported() {
+        let (mut app, _) = app_with_orders(|stop| vec![go_to(stop, Some(0.0))], false);
+        let delay = run_until_delay(&mut app);
+        assert!(delay > 0.0);
+        assert!(orders(&mut app).to_string().ends_with("s late"));
+
+        let (mut app, _) = app_with_orders(|stop| vec![go_to(stop, Some(1000.0))], false);
+        let delay = run_until_delay(&mut app);
+        assert!(delay < 0.0);
+        assert!(orders(&mut app).to_string().ends_with("s early"));
+    }
+
+    #[test]
+    fn repeating_orders_learn_their_schedule() {
+        let (mut app, stop) = app_with_orders(|stop| vec![go_to(stop, None)], true);
+        assert_eq!(run_until_delay(&mut app), 0.0);
+        let orders = orders(&mut app);
+        let Some(Order::GoTo {
+            arrival: Some(arrival),
+            ..
+        }) = orders.orders.first()
+        else {
+            panic!("The arrival wasn't scheduled");
+        };
+        assert!(*arrival > 0.0);
+        assert_eq!(orders.orders[0], go_to(stop, Some(*arrival)));
+    }
+
+    #[test]
+    fn unreachable_joints_are_not_resolved() {
+        let start = Joint {
+            tile: Tile(0, 0),
+            side: Direction::EAST,
+        };
+        let mut rail_graph = RailGraph::default();
+        for joint in [start, start.next_straight()] {
+            rail_graph.add_double_track(Track {
+                joint,
+                heading: TrackType::Straight,
+            });
+        }
+        let end = start.next_straight().next_straight();
+        let elsewhere = Joint {
+            tile: Tile(5, 5),
+            side: Direction::EAST,
+        };
+        rail_graph.add_double_track(Track {
+            joint: elsewhere,
+            heading: TrackType::Straight,
+        });
+
+        let resolve = |joint| resolve_stop(&Stop::Joint(joint), start, &rail_graph, [].iter());
+        assert_eq!(resolve(end), Some(end));
+        // Arriving from the other direction
+        assert_eq!(resolve(end.opposite()), Some(end.opposite()));
+        assert_eq!(resolve(elsewhere), None);
+        assert_eq!(resolve(elsewhere.next_straight()), None);
+    }
+}
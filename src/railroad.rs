@@ -1,18 +1,48 @@
+use crate::commands::SimCommand;
+use crate::construction::{BuildRules, Construction, ConstructionCosts};
 use crate::input::BuildingState;
 use crate::input::MenuState;
-use crate::interact::TileClickEvent;
+use crate::interact::{CursorWorldPos, TileClickEvent};
+use crate::routing::plan_tracks;
+use crate::sprites::BaseSpriteBundle;
 use crate::sprites::RailSprite;
 use crate::sprites::SpriteAssets;
+use crate::terrain::TerrainMap;
 use crate::tilemap::Joint;
+use crate::trains::Trail;
 
-use bevy::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
 use petgraph::graphmap::DiGraphMap;
+use petgraph::EdgeDirection;
 use serde::{Deserialize, Serialize};
 
 pub struct RailRoadPlugin;
 impl Plugin for RailRoadPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, rail_builder.run_if(in_state(MenuState::Building)));
+        app.init_resource::<RailGraph>()
+            .init_resource::<BuildRules>()
+            .init_resource::<ConstructionCosts>();
+    }
+}
+
+/// Building tracks in building mode, and the sprites of the switches.
+pub struct RailRoadUiPlugin;
+impl Plugin for RailRoadUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (rail_builder, drag_tracks.after(rail_builder)).run_if(in_state(MenuState::Building)),
+        )
+        .add_systems(
+            Update,
+            update_track_ghost
+                .after(drag_tracks)
+                .run_if(in_state(MenuState::Building)),
+        )
+        .add_systems(OnExit(MenuState::Building), clear_track_previews)
+        .add_systems(PostUpdate, (update_switch_markers, update_track_preview));
     }
 }
 
@@ -22,7 +52,38 @@ pub struct NetworkRoot;
 #[derive(Component)]
 pub struct RailMarker;
 
-#[derive(Serialize, Deserialize, Resource)]
+/// Marks the sprite highlighting the selected track of a switch.
+#[derive(Component)]
+pub struct SwitchMarker;
+
+/// Marks the translucent sprites of the tracks being dragged out.
+#[derive(Component)]
+struct TrackPreview;
+
+/// The translucent sprite of the track a click would lay, following the cursor.
+#[derive(Component, Debug, PartialEq)]
+struct TrackGhost {
+    track: Track,
+    can_build: bool,
+}
+
+/// The tint of previewed tracks which would be built.
+const PREVIEW_BUILD_COLOR: Srgba = Srgba::new(0.3, 1.0, 0.3, 0.5);
+/// The tint of previewed tracks which can't be built or already exist.
+const PREVIEW_BLOCKED_COLOR: Srgba = Srgba::new(1.0, 0.3, 0.3, 0.5);
+
+/// The tracks dragged out in building mode, from pressing the mouse button until releasing it.
+#[derive(Resource, Debug)]
+struct TrackDrag {
+    /// Where the first track starts.
+    start: Joint,
+    /// The joint under the cursor, the last track leaves its tile through this edge.
+    cursor: Joint,
+    /// Empty if the cursor is still at `start` or no tracks lead to the cursor.
+    tracks: Vec<Track>,
+}
+
+#[derive(Serialize, Deserialize, Resource, Default)]
 pub struct RailGraph {
     /// The underlying directed graph of the rail network.
     ///
@@ -35,12 +96,37 @@ pub struct RailGraph {
     /// - v is either 1 tile straight on, or 1 tile 60deg curved in either direction from u.
     /// - v.opposite() -> u.opposite() is also in G.
     pub graph: DiGraphMap<Joint, TrackProperties>,
+
+    /// The selected track for joints with multiple outgoing tracks, i.e. the switches.
+    ///
+    /// Joints without an entry (or with an entry for a track that doesn't exist)
+    /// default to going straight if possible, see [`RailGraph::route_from`].
+    #[serde(default, with = "switch_serde")]
+    pub switches: BTreeMap<Joint, TrackType>,
+
+    /// The joints with a block signal for trains passing in the direction of the joint.
+    ///
+    /// See the [`signals`](crate::signals) module.
+    #[serde(default)]
+    pub signals: BTreeSet<Joint>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct TrackProperties {}
+/// The speed limits in m/s, which can be selected in building mode.
+const SPEED_LIMIT_STEPS: [f32; 5] = [10.0, 20.0, 30.0, 40.0, 55.0];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TrackProperties {
+    /// The maximum velocity in m/s on this track.
+    /// If not set, the default for the [`TrackType`] applies, see [`RailGraph::speed_limit`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_limit: Option<f32>,
+}
+
+/// A single track, going from `joint` in the direction of `heading`.
+///
+/// Also attached to the [`RailMarker`] sprite entities, which is needed to find the
+/// sprite again when the track gets removed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Track {
     pub joint: Joint,
     pub heading: TrackType,
@@ -53,6 +139,22 @@ pub enum TrackType {
     CurvedRight,
 }
 
+/// The length of a curved track relative to a straight one, i.e. `PI / (2 * sqrt(3))`.
+///
+/// Curves are 60° arcs with a radius of `0.75 * TILE_SCALE`,
+/// while straight tracks are [`TILE_WIDTH`](crate::tilemap::TILE_WIDTH) long.
+pub const CURVE_LENGTH: f32 = 0.906_899_7;
+
+impl TrackType {
+    /// The speed limit in m/s for tracks without an explicit one.
+    pub fn default_speed_limit(&self) -> f32 {
+        match self {
+            TrackType::Straight => 55.0,
+            TrackType::CurvedLeft | TrackType::CurvedRight => 20.0,
+        }
+    }
+}
+
 impl Track {
     pub fn from_joints(start: Joint, end: Joint) -> Option<Self> {
         if start.next_left() == end {
@@ -83,6 +185,42 @@ impl Track {
         }
     }
 
+    /// The length of this track, where straight tracks have length 1.
+    pub fn length(&self) -> f32 {
+        match self.heading {
+            TrackType::Straight => 1.0,
+            TrackType::CurvedLeft | TrackType::CurvedRight => CURVE_LENGTH,
+        }
+    }
+
+    /// Rotates the track counterclockwise around the origin by `sixth_turns`.
+    pub fn rotated(&self, sixth_turns: i32) -> Self {
+        Self {
+            joint: self.joint.rotated(sixth_turns),
+            heading: self.heading,
+        }
+    }
+
+    /// Mirrors the track along the east-west axis through the origin,
+    /// which turns left curves into right ones and vice versa.
+    pub fn mirrored(&self) -> Self {
+        let heading = match self.heading {
+            TrackType::Straight => TrackType::Straight,
+            TrackType::CurvedLeft => TrackType::CurvedRight,
+            TrackType::CurvedRight => TrackType::CurvedLeft,
+        };
+        Self {
+            joint: self.joint.mirrored(),
+            heading,
+        }
+    }
+
+    /// Returns the same track, but traversed in the opposite direction.
+    pub fn reversed(&self) -> Self {
+        Track::from_joints(self.end_joint().opposite(), self.joint.opposite())
+            .expect("Invariant: a reversed track is also a track")
+    }
+
     pub fn is_canonical_orientation(&self) -> bool {
         use crate::tilemap::Direction as Dir;
         match (self.joint.side, self.heading) {
@@ -100,16 +238,20 @@ impl Track {
 }
 
 impl RailGraph {
+    pub fn contains_track(&self, track: Track) -> bool {
+        self.graph.contains_edge(track.joint, track.end_joint())
+    }
+
     /// Returns true if the track was added, false if it already existed.
     pub fn add_double_track(&mut self, track: Track) -> bool {
         let end_joint = track.end_joint();
         let prev_edge_1 = self
             .graph
-            .add_edge(track.joint, end_joint, TrackProperties {});
+            .add_edge(track.joint, end_joint, TrackProperties::default());
         let prev_edge_2 = self.graph.add_edge(
             end_joint.opposite(),
             track.joint.opposite(),
-            TrackProperties {},
+            TrackProperties::default(),
         );
         debug!("Rail built @{:?} -> {:?}", track.joint.tile, end_joint.tile);
 
@@ -119,65 +261,563 @@ impl RailGraph {
         }
         prev_edge_1.is_none()
     }
+
+    /// Returns true if the track was removed, false if it didn't exist.
+    ///
+    /// Joints which are not connected to any other track anymore are removed aswell.
+    pub fn remove_double_track(&mut self, track: Track) -> bool {
+        let end_joint = track.end_joint();
+        let removed_edge_1 = self.graph.remove_edge(track.joint, end_joint);
+        let removed_edge_2 = self
+            .graph
+            .remove_edge(end_joint.opposite(), track.joint.opposite());
+
+        // Either both or neither edge should have existed
+        if removed_edge_1.is_none() != removed_edge_2.is_none() {
+            error!("Invariant was broken: track in graph wasn't double track {track:?}");
+        }
+
+        for joint in [
+            track.joint,
+            end_joint,
+            track.joint.opposite(),
+            end_joint.opposite(),
+        ] {
+            let is_orphaned = self
+                .graph
+                .neighbors_directed(joint, EdgeDirection::Outgoing)
+                .chain(self.graph.neighbors_directed(joint, EdgeDirection::Incoming))
+                .next()
+                .is_none();
+            if is_orphaned {
+                self.graph.remove_node(joint);
+                self.signals.remove(&joint);
+            }
+        }
+        for joint in [track.joint, end_joint.opposite()] {
+            if !self.is_switch(joint) {
+                self.switches.remove(&joint);
+            }
+        }
+        if removed_edge_1.is_some() {
+            debug!("Rail removed @{:?} -> {:?}", track.joint.tile, end_joint.tile);
+        }
+
+        removed_edge_1.is_some()
+    }
+
+    /// Returns all the tracks starting at `joint`.
+    pub fn tracks_from(&self, joint: Joint) -> Vec<Track> {
+        self.graph
+            .neighbors_directed(joint, EdgeDirection::Outgoing)
+            .map(|next| {
+                Track::from_joints(joint, next).expect("Invariant: graph only has track edges")
+            })
+            .collect()
+    }
+
+    /// Returns true if there are multiple tracks starting at `joint`.
+    pub fn is_switch(&self, joint: Joint) -> bool {
+        self.graph
+            .neighbors_directed(joint, EdgeDirection::Outgoing)
+            .nth(1)
+            .is_some()
+    }
+
+    /// Returns the track a train at `joint` will take, which respects the switch setting.
+    pub fn route_from(&self, joint: Joint) -> Option<Track> {
+        let tracks = self.tracks_from(joint);
+        let preferred = self
+            .switches
+            .get(&joint)
+            .copied()
+            .unwrap_or(TrackType::Straight);
+        tracks
+            .iter()
+            .find(|track| track.heading == preferred)
+            .or(tracks.first())
+            .copied()
+    }
+
+    /// Adds a signal at `joint` if there is none, otherwise removes it.
+    ///
+    /// Returns true if there is a signal now, signals can only be placed on the network.
+    pub fn toggle_signal(&mut self, joint: Joint) -> bool {
+        if self.signals.remove(&joint) {
+            debug!("Signal removed @{:?}", joint.tile);
+            return false;
+        }
+        if !self.graph.contains_node(joint) {
+            return false;
+        }
+        debug!("Signal placed @{:?}", joint.tile);
+        self.signals.insert(joint)
+    }
+
+    /// Returns the speed limit in m/s of the track from `from` to `to`, if it exists.
+    pub fn speed_limit(&self, from: Joint, to: Joint) -> Option<f32> {
+        let properties = self.graph.edge_weight(from, to)?;
+        properties
+            .speed_limit
+            .or_else(|| Track::from_joints(from, to).map(|t| t.heading.default_speed_limit()))
+    }
+
+    /// Sets the speed limit of `track` in both directions, `None` resets it to the default.
+    pub fn set_speed_limit(&mut self, track: Track, speed_limit: Option<f32>) {
+        let reversed = track.reversed();
+        for t in [track, reversed] {
+            if let Some(properties) = self.graph.edge_weight_mut(t.joint, t.end_joint()) {
+                properties.speed_limit = speed_limit;
+            }
+        }
+    }
+
+    /// Sets the speed limit of the tracks starting at `joint` to the next of the
+    /// [`SPEED_LIMIT_STEPS`], starting over after the highest one.
+    pub fn cycle_speed_limit(&mut self, joint: Joint) {
+        for track in self.tracks_from(joint) {
+            let Some(current) = self.speed_limit(track.joint, track.end_joint()) else {
+                continue;
+            };
+            let next = SPEED_LIMIT_STEPS
+                .into_iter()
+                .find(|&step| step > current)
+                .unwrap_or(SPEED_LIMIT_STEPS[0]);
+            self.set_speed_limit(track, Some(next));
+            info!("Speed limit @{:?} set to {next} m/s", track.joint.tile);
+        }
+    }
+
+    /// Returns true if a signal in either direction separates two blocks at `joint`.
+    pub fn is_block_boundary(&self, joint: Joint) -> bool {
+        self.signals.contains(&joint) || self.signals.contains(&joint.opposite())
+    }
+
+    /// Sets the switch at `joint` to the track with `heading`.
+    ///
+    /// Returns false if `joint` isn't a switch or has no such track.
+    pub fn set_switch(&mut self, joint: Joint, heading: TrackType) -> bool {
+        let has_track = self.tracks_from(joint).iter().any(|t| t.heading == heading);
+        if !self.is_switch(joint) || !has_track {
+            return false;
+        }
+        self.switches.insert(joint, heading);
+        debug!("Switch @{:?} set to {heading:?}", joint.tile);
+        true
+    }
 }
 
-/// This system tries to build rails both in the graph and with sprites when the mouse is clicked.
+/// Serializes the switches as a list of pairs, since json only supports string keys.
+mod switch_serde {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::TrackType;
+    use crate::tilemap::Joint;
+
+    pub fn serialize<S>(
+        switches: &BTreeMap<Joint, TrackType>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(switches.iter())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BTreeMap<Joint, TrackType>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pairs = Vec::<(Joint, TrackType)>::deserialize(deserializer)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+/// This system turns clicks in building mode into [`SimCommand`]s to build or demolish rails,
+/// place signals and set speed limits. Tracks are built on release, see [`drag_tracks`].
 fn rail_builder(
     mut commands: Commands,
-    assets: Res<SpriteAssets>,
     mut click_event: MessageReader<TileClickEvent>,
-    mut rail_graph: ResMut<RailGraph>,
-    root_query: Query<Entity, With<NetworkRoot>>,
+    mut sim_commands: MessageWriter<SimCommand>,
     state: Res<State<BuildingState>>,
 ) {
-    let BuildingState::LayTrack(rail_type) = *state.get();
-    let rail_graph = rail_graph.as_mut();
-    let root_entity = root_query.single().expect("exactly one NetworkRoot entity");
-
     for evt in click_event.read() {
-        if let Some(side) = evt.side {
-            match evt.button {
-                MouseButton::Left => {
-                    let track = Track {
-                        joint: Joint {
-                            tile: evt.coord,
-                            side,
-                        },
-                        heading: rail_type,
-                    };
-                    let is_new_track = rail_graph.add_double_track(track);
-                    if is_new_track {
-                        commands.entity(root_entity).with_children(|c| {
-                            c.spawn(rail_tile_bundle(&assets, track));
-                        });
-                    }
-                }
-                _ => (),
+        let Some(side) = evt.side else {
+            continue;
+        };
+        if evt.button != MouseButton::Left {
+            continue;
+        }
+        let joint = Joint {
+            tile: evt.coord,
+            side,
+        };
+        let command = match *state.get() {
+            BuildingState::LayTrack(_) => {
+                commands.insert_resource(TrackDrag {
+                    start: joint,
+                    cursor: joint,
+                    tracks: Vec::new(),
+                });
+                continue;
+            }
+            BuildingState::PlaceSignal => SimCommand::ToggleSignal(joint),
+            BuildingState::SetSpeedLimit => SimCommand::CycleSpeedLimit(joint),
+            BuildingState::Demolish => SimCommand::Demolish(joint),
+            BuildingState::PlaceStation
+            | BuildingState::CopyBlueprint
+            | BuildingState::PasteBlueprint => continue,
+        };
+        sim_commands.write(command);
+    }
+}
+
+/// This system plans the tracks from where the mouse button was pressed to the cursor,
+/// and lays them all with one [`SimCommand`] when the button is released.
+///
+/// Releasing the button where it was pressed lays a single track of the selected
+/// [`TrackType`] instead, or selects it at a switch.
+fn drag_tracks(
+    mut commands: Commands,
+    drag: Option<ResMut<TrackDrag>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    cursor: Res<CursorWorldPos>,
+    state: Res<State<BuildingState>>,
+    build_preview: BuildPreview,
+    mut sim_commands: MessageWriter<SimCommand>,
+) {
+    let Some(mut drag) = drag else {
+        return;
+    };
+    if !mouse_input.pressed(MouseButton::Left) {
+        commands.remove_resource::<TrackDrag>();
+        if drag.cursor == drag.start {
+            if let BuildingState::LayTrack(heading) = *state.get() {
+                let joint = drag.start;
+                sim_commands.write(SimCommand::LayTrack(Track { joint, heading }));
             }
+        } else if drag.tracks.is_empty() {
+            warn!(
+                "Cannot build tracks from {:?} to {:?}",
+                drag.start.tile, drag.cursor.tile
+            );
+        } else {
+            sim_commands.write(SimCommand::LayTracks(std::mem::take(&mut drag.tracks)));
+        }
+        return;
+    }
+
+    // Near the center of a tile the joint is ambiguous, keep the last one then
+    let Some(Ok(cursor)) = cursor.0.map(Joint::from_world_pos) else {
+        return;
+    };
+    if cursor == drag.cursor {
+        return;
+    }
+    drag.cursor = cursor;
+    drag.tracks = if cursor == drag.start {
+        Vec::new()
+    } else {
+        let is_allowed = |track: Track| {
+            build_preview.rail_graph.contains_track(track) || build_preview.can_build(track)
+        };
+        // The last track ends at the joint opposite of the edge under the cursor
+        plan_tracks(drag.start, cursor.opposite(), is_allowed).unwrap_or_default()
+    };
+}
+
+/// This system moves the [`TrackGhost`] along with the cursor, while a track type is selected.
+///
+/// The ghost snaps to the joint a click would use, and is hidden while tracks are
+/// dragged out, since those are previewed already.
+fn update_track_ghost(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    state: Res<State<BuildingState>>,
+    drag: Option<Res<TrackDrag>>,
+    cursor: Res<CursorWorldPos>,
+    build_preview: BuildPreview,
+    ghosts: Query<(Entity, &TrackGhost)>,
+) {
+    let ghost = match (state.get(), cursor.0.map(Joint::from_world_pos)) {
+        (&BuildingState::LayTrack(heading), Some(Ok(joint))) if drag.is_none() => {
+            let track = Track { joint, heading };
+            Some(TrackGhost {
+                track,
+                can_build: !build_preview.rail_graph.contains_track(track)
+                    && build_preview.can_build(track),
+            })
         }
+        _ => None,
+    };
+    if ghosts.iter().next().map(|(_, shown)| shown) == ghost.as_ref() {
+        return;
+    }
+
+    for (entity, _) in &ghosts {
+        commands.entity(entity).despawn();
+    }
+    let Some(ghost) = ghost else {
+        return;
+    };
+    let sprite = track_preview_sprite(&assets, ghost.track, ghost.can_build);
+    commands.spawn((sprite, Name::new("Track ghost"), ghost));
+}
+
+/// Leaving building mode drops the tracks being dragged out and the [`TrackGhost`].
+fn clear_track_previews(mut commands: Commands, ghosts: Query<Entity, With<TrackGhost>>) {
+    commands.remove_resource::<TrackDrag>();
+    for ghost in &ghosts {
+        commands.entity(ghost).despawn();
+    }
+}
+
+/// Read-only access to everything deciding whether a track can be built,
+/// see [`Construction`] for building it.
+#[derive(SystemParam)]
+pub(crate) struct BuildPreview<'w> {
+    rules: Res<'w, BuildRules>,
+    terrain: Res<'w, TerrainMap>,
+    pub rail_graph: Res<'w, RailGraph>,
+}
+
+impl BuildPreview<'_> {
+    /// Whether the [`BuildRules`] allow `track`, regardless of whether it exists already.
+    pub fn can_build(&self, track: Track) -> bool {
+        self.rules.check(track, &self.terrain).is_ok()
+    }
+}
+
+/// Builds `track` both in the graph and as an entity, or selects it if it is part of a switch.
+///
+/// New tracks have to be allowed by the [`BuildRules`], otherwise the reason is reported.
+pub(crate) fn lay_track(
+    In(track): In<Track>,
+    mut commands: Commands,
+    assets: Option<Res<SpriteAssets>>,
+    mut rail_graph: ResMut<RailGraph>,
+    root_query: Query<Entity, With<NetworkRoot>>,
+    mut construction: Construction,
+) {
+    if rail_graph
+        .graph
+        .contains_edge(track.joint, track.end_joint())
+    {
+        // Clicking an existing track of a switch selects it.
+        rail_graph.set_switch(track.joint, track.heading);
+        return;
+    }
+    let permit = match construction.check(track) {
+        Ok(permit) => permit,
+        Err(reason) => {
+            warn!("Cannot build a track @{:?}: {reason}", track.joint.tile);
+            return;
+        }
+    };
+    if !rail_graph.add_double_track(track) {
+        return;
+    }
+    construction.costs.0 += permit.cost;
+    debug!(
+        "{:?} @{:?} cost {}, {} in total",
+        permit.structure, track.joint.tile, permit.cost, construction.costs.0
+    );
+    // Without assets, e.g. in a headless simulation, the sprite is simply invisible
+    let default_assets = SpriteAssets::default();
+    let assets = assets.as_deref().unwrap_or(&default_assets);
+    let rail = commands.spawn(rail_tile_bundle(assets, track)).id();
+    if let Ok(root_entity) = root_query.single() {
+        commands.entity(root_entity).add_child(rail);
+    }
+}
+
+/// Demolishes the tracks starting at `joint`, unless a train is standing on them.
+pub(crate) fn demolish_tracks(
+    In(joint): In<Joint>,
+    mut commands: Commands,
+    mut rail_graph: ResMut<RailGraph>,
+    rail_sprites: Query<(Entity, &Track), With<RailMarker>>,
+    mut trails: Query<&mut Trail>,
+) {
+    for track in rail_graph.tracks_from(joint) {
+        remove_track(
+            track,
+            &mut commands,
+            &mut rail_graph,
+            &rail_sprites,
+            &mut trails,
+        );
+    }
+}
+
+/// Demolishes exactly the given tracks, unless a train is standing on them.
+pub(crate) fn demolish_exact_tracks(
+    In(tracks): In<Vec<Track>>,
+    mut commands: Commands,
+    mut rail_graph: ResMut<RailGraph>,
+    rail_sprites: Query<(Entity, &Track), With<RailMarker>>,
+    mut trails: Query<&mut Trail>,
+) {
+    for track in tracks {
+        remove_track(
+            track,
+            &mut commands,
+            &mut rail_graph,
+            &rail_sprites,
+            &mut trails,
+        );
+    }
+}
+
+/// Removes `track` from the graph together with its sprite, see [`demolish_tracks`].
+fn remove_track(
+    track: Track,
+    commands: &mut Commands,
+    rail_graph: &mut RailGraph,
+    rail_sprites: &Query<(Entity, &Track), With<RailMarker>>,
+    trails: &mut Query<&mut Trail>,
+) {
+    if trails.iter().any(|trail| trail.covers_track(track)) {
+        warn!("Cannot demolish track {track:?}, a train is standing on it");
+        return;
+    }
+    if !rail_graph.remove_double_track(track) {
+        return;
+    }
+
+    // The sprite was spawned with either of the two orientations.
+    let reversed = track.reversed();
+    for (sprite_id, _) in rail_sprites
+        .iter()
+        .filter(|&(_, &t)| t == track || t == reversed)
+    {
+        commands.entity(sprite_id).despawn();
+    }
+    // Trains must not drive onto the removed track with their lead.
+    for mut trail in trails.iter_mut() {
+        trail.cut_lead_at_track(track);
+    }
+}
+
+/// This system respawns the sprites highlighting the selected track of every switch,
+/// whenever the [`RailGraph`] changed.
+fn update_switch_markers(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    rail_graph: Res<RailGraph>,
+    root_query: Query<Entity, With<NetworkRoot>>,
+    markers: Query<Entity, With<SwitchMarker>>,
+) {
+    if !rail_graph.is_changed() {
+        return;
+    }
+    let Ok(root_entity) = root_query.single() else {
+        // Not loaded yet
+        return;
+    };
+
+    for marker in &markers {
+        commands.entity(marker).despawn();
+    }
+    for joint in rail_graph.graph.nodes() {
+        if !rail_graph.is_switch(joint) {
+            continue;
+        }
+        let Some(track) = rail_graph.route_from(joint) else {
+            continue;
+        };
+        let sprite = track_sprite(&assets, track, SpriteAssets::switch_sprite);
+        commands.entity(root_entity).with_children(|c| {
+            c.spawn((
+                sprite,
+                Name::new(format!("Switch {:?}", joint.tile)),
+                SwitchMarker,
+            ));
+        });
+    }
+}
+
+/// This system respawns the translucent sprites of the tracks being dragged out,
+/// whenever they change. Tracks which already exist aren't shown.
+fn update_track_preview(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    drag: Option<Res<TrackDrag>>,
+    rail_graph: Res<RailGraph>,
+    previews: Query<Entity, With<TrackPreview>>,
+) {
+    if drag.as_ref().is_some_and(|drag| !drag.is_changed()) {
+        return;
+    }
+    for preview in &previews {
+        commands.entity(preview).despawn();
+    }
+    let Some(drag) = drag else {
+        return;
+    };
+    for &track in &drag.tracks {
+        if rail_graph.contains_track(track) {
+            continue;
+        }
+        // Only tracks which can be built are planned
+        commands.spawn((
+            track_preview_sprite(&assets, track, true),
+            Name::new(format!("Preview {:?}", track.joint.tile)),
+            TrackPreview,
+        ));
     }
 }
 
 /// Generates a bundle for a track tile entity
 pub fn rail_tile_bundle(assets: &SpriteAssets, track: Track) -> impl Bundle {
+    (
+        track_sprite(assets, track, SpriteAssets::rail_sprite),
+        Name::new(format!("Rail {:?}", track.joint.tile)),
+        RailMarker,
+        track,
+        track.joint.tile,
+    )
+}
+
+/// A translucent sprite previewing `track`, green if it would be built and red otherwise.
+pub(crate) fn track_preview_sprite(
+    assets: &SpriteAssets,
+    track: Track,
+    can_build: bool,
+) -> BaseSpriteBundle {
+    let mut sprite = track_sprite(assets, track, SpriteAssets::preview_sprite);
+    sprite.sprite.color = if can_build {
+        PREVIEW_BUILD_COLOR
+    } else {
+        PREVIEW_BLOCKED_COLOR
+    }
+    .into();
+    sprite
+}
+
+/// Helper to orient and place a rail sprite from `sprite_fn` such that it matches `track`.
+fn track_sprite(
+    assets: &SpriteAssets,
+    track: Track,
+    sprite_fn: fn(&SpriteAssets, RailSprite) -> BaseSpriteBundle,
+) -> BaseSpriteBundle {
     let flipped = match track.heading {
         TrackType::Straight => false,
         TrackType::CurvedLeft => true,
         TrackType::CurvedRight => false,
     };
-    let mut sprite = assets.rail_sprite(match track.heading {
-        TrackType::Straight => RailSprite::Straight,
-        TrackType::CurvedLeft => RailSprite::CurvedRight,
-        TrackType::CurvedRight => RailSprite::CurvedRight,
-    });
+    let mut sprite = sprite_fn(
+        assets,
+        match track.heading {
+            TrackType::Straight => RailSprite::Straight,
+            TrackType::CurvedLeft => RailSprite::CurvedRight,
+            TrackType::CurvedRight => RailSprite::CurvedRight,
+        },
+    );
     sprite.sprite.flip_y = flipped;
     sprite.transform.rotate_z(track.joint.side.to_angle());
     sprite.transform.translation += track.joint.tile.world_pos().extend(0.);
-
-    (
-        sprite,
-        Name::new(format!("Rail {:?}", track.joint.tile)),
-        RailMarker,
-        track.joint.tile,
-    )
+    sprite
 }
@@ -0,0 +1,206 @@
+//! Recording the [`SimCommand`]s of a session and replaying them.
+//!
+//! The simulation only changes in `FixedUpdate` steps, with a fixed delta time, and the
+//! player only influences it with [`SimCommand`]s applied at the start of a step. So a
+//! savegame plus the commands and the [`FixedTick`] they were applied at, a [`CommandLog`],
+//! reproduce a session exactly, e.g. with the `hexrails-replay` binary.
+//!
+//! In the game, F9 starts a recording: the game is saved to the slot [`REPLAY_START_SLOT`]
+//! and reloaded from it, such that the world matches the one of a replay. Pressing F9 again
+//! writes the log to [`REPLAY_LOG_FILE`] and the game to the slot [`REPLAY_END_SLOT`].
+
+use std::{error::Error, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::SimCommand;
+
+/// The file the log of the last recording is written to.
+pub const REPLAY_LOG_FILE: &str = "savegame/replay.log";
+/// The slot of the game at the start of the last recording.
+pub const REPLAY_START_SLOT: &str = "replay-start";
+/// The slot of the game at the end of the last recording.
+pub const REPLAY_END_SLOT: &str = "replay-end";
+
+/// Counts the `FixedUpdate` steps, part of the [`SimulationPlugins`](crate::SimulationPlugins).
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FixedTick>()
+            .init_resource::<Replay>()
+            .add_systems(FixedLast, count_ticks);
+    }
+}
+
+/// The number of `FixedUpdate` steps since the game was loaded.
+#[derive(
+    Resource, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct FixedTick(pub u64);
+
+/// A command applied at the start of the fixed step `tick`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LoggedCommand {
+    pub tick: FixedTick,
+    pub command: SimCommand,
+}
+
+/// All commands of a recording, starting at tick 0 with the start savegame.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CommandLog {
+    /// Sorted by tick.
+    pub entries: Vec<LoggedCommand>,
+    /// The tick the recording was stopped at, i.e. the number of steps to replay.
+    pub end_tick: FixedTick,
+}
+
+impl CommandLog {
+    pub fn record(&mut self, tick: FixedTick, command: SimCommand) {
+        self.entries.push(LoggedCommand { tick, command });
+    }
+
+    /// Returns the commands for `tick`, starting at the entry `next`, which is advanced past them.
+    pub fn due(&self, tick: FixedTick, next: &mut usize) -> &[LoggedCommand] {
+        let start = (*next).min(self.entries.len());
+        let count = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.tick <= tick)
+            .count();
+        *next = start + count;
+        &self.entries[start..*next]
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+/// Whether commands are recorded or replayed.
+#[derive(Resource, Debug, Default)]
+pub enum Replay {
+    #[default]
+    Off,
+    /// The commands issued by the player are added to the log.
+    Recording(CommandLog),
+    /// The commands of the log are applied instead of the ones of the player,
+    /// `next` is the index of the next entry to apply.
+    Playing { log: CommandLog, next: usize },
+}
+
+impl Replay {
+    /// Replays `log`, the world has to be loaded from the start savegame at tick 0.
+    pub fn play(log: CommandLog) -> Self {
+        Replay::Playing { log, next: 0 }
+    }
+
+    /// Returns true when a replay has applied all commands and reached its end tick.
+    pub fn is_finished(&self, tick: FixedTick) -> bool {
+        match self {
+            Replay::Playing { log, next } => *next >= log.entries.len() && tick >= log.end_tick,
+            _ => false,
+        }
+    }
+}
+
+// ================================ SYSTEMS ===================================
+
+fn count_ticks(mut tick: ResMut<FixedTick>) {
+    tick.0 += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use crate::headless_app;
+    use crate::orders::{Order, Stop};
+    use crate::railroad::{Track, TrackType};
+    use crate::savegame;
+    use crate::tilemap::Joint;
+    use crate::trains::{Controller, TrainId, VehicleType};
+
+    /// Writes `commands` and runs `steps` fixed steps.
+    fn run(app: &mut App, commands: impl IntoIterator<Item = SimCommand>, steps: usize) {
+        for command in commands {
+            app.world_mut().write_message(command);
+        }
+        for _ in 0..steps {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn replay_ends_in_the_recorded_game() {
+        let line: Vec<Joint> =
+            iter::successors(Some(Joint::default()), |joint| Some(joint.next_straight()))
+                .take(20)
+                .collect();
+        let train = TrainId(1);
+        let set_controller = |throttle, brake| SimCommand::SetController {
+            train,
+            controller: Controller { throttle, brake },
+        };
+
+        let mut app = headless_app();
+        app.update();
+        app.insert_resource(Replay::Recording(CommandLog::default()));
+        let tracks = line
+            .iter()
+            .map(|&joint| Track {
+                joint,
+                heading: TrackType::Straight,
+            })
+            .collect();
+        let spawn = SimCommand::SpawnTrain {
+            joint: line[0],
+            vehicle_type: VehicleType::Locomotive,
+        };
+        run(&mut app, [SimCommand::LayTracks(tracks), spawn], 1);
+        let steer = SimCommand::Steer {
+            train,
+            direction: Some(TrackType::CurvedLeft),
+        };
+        run(&mut app, [set_controller(1.0, 0.0), steer], 100);
+        let orders = SimCommand::AppendOrders {
+            train,
+            orders: vec![Order::GoTo {
+                stop: Stop::Station("Station 1".to_string()),
+                arrival: None,
+            }],
+            repeat: true,
+        };
+        let station = SimCommand::ToggleStation(line[10]);
+        let signal = SimCommand::ToggleSignal(line[15]);
+        run(&mut app, [station, signal, orders], 100);
+        let clear = SimCommand::ClearOrders(train);
+        run(&mut app, [clear, set_controller(0.0, 1.0)], 100);
+
+        let tick = *app.world().resource::<FixedTick>();
+        let Some(Replay::Recording(mut log)) = app.world_mut().remove_resource::<Replay>() else {
+            panic!("The game wasn't recorded");
+        };
+        assert_eq!(log.entries.len(), 9);
+        log.end_tick = tick;
+        let recorded = savegame::to_json(app.world_mut()).unwrap();
+
+        let mut app = headless_app();
+        app.update();
+        app.insert_resource(Replay::play(log));
+        while !app
+            .world()
+            .resource::<Replay>()
+            .is_finished(*app.world().resource::<FixedTick>())
+        {
+            app.update();
+        }
+        assert_eq!(savegame::to_json(app.world_mut()).unwrap(), recorded);
+    }
+}
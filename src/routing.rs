@@ -0,0 +1,316 @@
+//! This module implements route planning on the [`RailGraph`] with A*.
+//!
+//! Routes only ever follow the direction of the edges, since trains can't turn on the spot.
+//! Trains which can change direction may reverse at a joint instead, see
+//! [`RailGraph::find_route_with_reversals`].
+//! The hex [`Tile`](crate::tilemap::Tile) distance is used as the heuristic, which is admissible
+//! since every track and every reversal moves at most one tile further.
+//!
+//! Planning new tracks with [`plan_tracks`] is a breadth-first search instead, since there
+//! are no tracks to follow yet.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use petgraph::EdgeDirection;
+
+use crate::railroad::{RailGraph, Track, TrackType};
+use crate::tilemap::Joint;
+
+/// The maximum number of tracks [`plan_tracks`] plans in one go.
+const MAX_PLANNED_TRACKS: usize = 64;
+
+impl RailGraph {
+    /// Finds the shortest route from `from` to `to` following the tracks.
+    ///
+    /// The route starts with `from` and ends with `to`,
+    /// such that it can be appended to a [`Trail::path`](crate::trains::Trail::path).
+    /// Returns `None` if `to` cannot be reached.
+    pub fn find_route(&self, from: Joint, to: Joint) -> Option<Vec<Joint>> {
+        self.astar(from, to, None)
+    }
+
+    /// Finds the shortest route from `from` to `to`, which may reverse direction at any joint.
+    ///
+    /// Reversing at a joint continues from its [`Joint::opposite`] and costs as much as
+    /// `reversal_cost` tracks (at least 1). The route is returned as legs, where the last joint
+    /// of a leg is the opposite of the first joint of the next one, i.e. the train reverses there.
+    ///
+    /// Note that the length of the train isn't considered, so a train reversing
+    /// might have to pull ahead of the reversal point first.
+    pub fn find_route_with_reversals(
+        &self,
+        from: Joint,
+        to: Joint,
+        reversal_cost: u32,
+    ) -> Option<Vec<Vec<Joint>>> {
+        let route = self.astar(from, to, Some(reversal_cost.max(1)))?;
+
+        let mut legs = vec![vec![route[0]]];
+        for pair in route.windows(2) {
+            if pair[1] == pair[0].opposite() {
+                legs.push(Vec::new());
+            }
+            legs.last_mut().expect("starts with one leg").push(pair[1]);
+        }
+        Some(legs)
+    }
+
+    /// A* search, where reversing at a joint is allowed if `reversal_cost` is given.
+    fn astar(&self, from: Joint, to: Joint, reversal_cost: Option<u32>) -> Option<Vec<Joint>> {
+        if !self.graph.contains_node(from) || !self.graph.contains_node(to) {
+            return None;
+        }
+        let heuristic = |joint: Joint| joint.tile.distance(to.tile);
+
+        // Min heap of (estimated total cost, cost so far, joint)
+        let mut queue = BinaryHeap::from([Reverse((heuristic(from), 0, from))]);
+        let mut best_costs = HashMap::from([(from, 0)]);
+        let mut came_from = HashMap::new();
+
+        while let Some(Reverse((_, cost, joint))) = queue.pop() {
+            if joint == to {
+                let mut route = vec![to];
+                while let Some(&previous) = came_from.get(route.last().expect("non-empty")) {
+                    route.push(previous);
+                }
+                route.reverse();
+                return Some(route);
+            }
+            if best_costs.get(&joint).is_some_and(|&best| best < cost) {
+                // Outdated entry, the joint was already reached cheaper
+                continue;
+            }
+
+            let reversal = reversal_cost.map(|c| (joint.opposite(), c));
+            let successors = self
+                .graph
+                .neighbors_directed(joint, EdgeDirection::Outgoing)
+                .map(|next| (next, 1))
+                .chain(reversal);
+            for (next, step_cost) in successors {
+                let next_cost = cost + step_cost;
+                if best_costs.get(&next).is_none_or(|&best| next_cost < best) {
+                    best_costs.insert(next, next_cost);
+                    came_from.insert(next, joint);
+                    queue.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Plans the fewest tracks leading from `from` to `to`, e.g. to drag out new tracks.
+///
+/// The first track starts at `from` and the last one ends at `to`, using only the tracks
+/// `is_allowed` accepts. Straight tracks are preferred over curves of the same count.
+/// Returns `None` if there are no such tracks, at most [`MAX_PLANNED_TRACKS`] of them.
+pub fn plan_tracks(
+    from: Joint,
+    to: Joint,
+    is_allowed: impl Fn(Track) -> bool,
+) -> Option<Vec<Track>> {
+    // The track leading to every reached joint
+    let mut previous: HashMap<Joint, Track> = HashMap::new();
+    let mut queue = VecDeque::from([(from, 0)]);
+    while let Some((joint, count)) = queue.pop_front() {
+        if joint == to {
+            let mut tracks = Vec::with_capacity(count);
+            let mut joint = to;
+            while joint != from {
+                let track = previous[&joint];
+                tracks.push(track);
+                joint = track.joint;
+            }
+            tracks.reverse();
+            return Some(tracks);
+        }
+        if count == MAX_PLANNED_TRACKS {
+            continue;
+        }
+        for heading in [
+            TrackType::Straight,
+            TrackType::CurvedLeft,
+            TrackType::CurvedRight,
+        ] {
+            let track = Track { joint, heading };
+            let next = track.end_joint();
+            if next == from || previous.contains_key(&next) || !is_allowed(track) {
+                continue;
+            }
+            previous.insert(next, track);
+            queue.push_back((next, count + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::{Direction, Tile};
+
+    /// The joint `x` tiles east of the origin, for tracks going west.
+    fn west(x: i32) -> Joint {
+        Joint {
+            tile: Tile(x, 0),
+            side: Direction::EAST,
+        }
+    }
+
+    fn straight(joint: Joint) -> Track {
+        Track {
+            joint,
+            heading: TrackType::Straight,
+        }
+    }
+
+    /// Straight tracks from `west(0)` to `west(-count)`.
+    fn straight_line(count: i32) -> RailGraph {
+        let mut rail_graph = RailGraph::default();
+        for x in 0..count {
+            rail_graph.add_double_track(straight(west(-x)));
+        }
+        rail_graph
+    }
+
+    #[test]
+    fn finds_route_along_tracks() {
+        let rail_graph = straight_line(3);
+        assert_eq!(
+            rail_graph.find_route(west(0), west(-3)),
+            Some(vec![west(0), west(-1), west(-2), west(-3)])
+        );
+        assert_eq!(
+            rail_graph.find_route(west(-1), west(-1)),
+            Some(vec![west(-1)])
+        );
+    }
+
+    #[test]
+    fn finds_shortest_route_at_switch() {
+        let mut rail_graph = straight_line(3);
+        // A curve leaving the line, which doesn't lead to the goal
+        rail_graph.add_double_track(Track {
+            joint: west(0),
+            heading: TrackType::CurvedLeft,
+        });
+        let route = rail_graph.find_route(west(0), west(-3)).unwrap();
+        assert_eq!(route.len(), 4);
+    }
+
+    #[test]
+    fn routes_follow_edge_direction() {
+        let rail_graph = straight_line(3);
+        assert_eq!(rail_graph.find_route(west(-3), west(0)), None);
+        // The same tracks, in the other direction
+        let back = rail_graph
+            .find_route(west(-3).opposite(), west(0).opposite())
+            .unwrap();
+        assert_eq!(back.len(), 4);
+        for pair in back.windows(2) {
+            assert!(rail_graph.graph.contains_edge(pair[0], pair[1]));
+        }
+    }
+
+    #[test]
+    fn reverses_into_siding() {
+        let mut rail_graph = straight_line(3);
+        // A siding branching off behind a train coming from `west(0)`
+        let siding = Track {
+            joint: west(-1).opposite(),
+            heading: TrackType::CurvedLeft,
+        };
+        rail_graph.add_double_track(siding);
+        let goal = siding.end_joint();
+
+        assert_eq!(rail_graph.find_route(west(0), goal), None);
+        assert_eq!(
+            rail_graph.find_route_with_reversals(west(0), goal, 3),
+            Some(vec![
+                vec![west(0), west(-1)],
+                vec![west(-1).opposite(), goal]
+            ])
+        );
+    }
+
+    #[test]
+    fn reverses_at_dead_end() {
+        let rail_graph = straight_line(3);
+        // The end of the line, facing back towards it
+        assert_eq!(
+            rail_graph.find_route_with_reversals(west(-1), west(-3).opposite(), 3),
+            Some(vec![
+                vec![west(-1), west(-2), west(-3)],
+                vec![west(-3).opposite()]
+            ])
+        );
+    }
+
+    #[test]
+    fn reverses_only_if_needed() {
+        let rail_graph = straight_line(3);
+        assert_eq!(
+            rail_graph.find_route_with_reversals(west(0), west(-3), 3),
+            Some(vec![vec![west(0), west(-1), west(-2), west(-3)]])
+        );
+    }
+
+    #[test]
+    fn unreachable_routes() {
+        let mut rail_graph = straight_line(2);
+        rail_graph.add_double_track(straight(west(-5)));
+        assert_eq!(rail_graph.find_route(west(0), west(-6)), None);
+        // Not in the graph at all
+        assert_eq!(rail_graph.find_route(west(0), west(-10)), None);
+        assert_eq!(rail_graph.find_route(west(10), west(-1)), None);
+    }
+
+    #[test]
+    fn plans_straight_tracks() {
+        let tracks = plan_tracks(west(0), west(-3), |_| true).unwrap();
+        assert_eq!(
+            tracks,
+            vec![straight(west(0)), straight(west(-1)), straight(west(-2))]
+        );
+        assert_eq!(plan_tracks(west(0), west(0), |_| true), Some(Vec::new()));
+    }
+
+    #[test]
+    fn planned_tracks_are_connected() {
+        // Leaving towards the west, arriving heading north-east
+        let to = Joint {
+            tile: Tile(-4, 3),
+            side: Direction::SOUTH_WEST,
+        };
+        let tracks = plan_tracks(west(0), to, |_| true).unwrap();
+        assert_eq!(tracks[0].joint, west(0));
+        assert_eq!(tracks.last().unwrap().end_joint(), to);
+        for pair in tracks.windows(2) {
+            assert_eq!(pair[0].end_joint(), pair[1].joint);
+        }
+        assert!(tracks
+            .iter()
+            .any(|track| track.heading != TrackType::Straight));
+    }
+
+    #[test]
+    fn plans_only_allowed_tracks() {
+        let blocked = Tile(-2, 0);
+        let tracks = plan_tracks(west(0), west(-4), |track| track.joint.tile != blocked).unwrap();
+        assert!(tracks.len() > 4);
+        assert!(tracks.iter().all(|track| track.joint.tile != blocked));
+        assert_eq!(plan_tracks(west(0), west(-4), |_| false), None);
+    }
+
+    #[test]
+    fn plans_at_most_max_tracks() {
+        // A narrow strip, to keep the search small
+        let in_strip = |track: Track| track.joint.tile.1.abs() <= 1;
+        let far = -(MAX_PLANNED_TRACKS as i32);
+        let tracks = plan_tracks(west(0), west(far), in_strip).unwrap();
+        assert_eq!(tracks.len(), MAX_PLANNED_TRACKS);
+        assert_eq!(plan_tracks(west(0), west(far - 1), in_strip), None);
+    }
+}
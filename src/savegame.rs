@@ -1,29 +1,61 @@
-use std::{error::Error, fs};
+use std::{error::Error, fs, path::Path, time::Duration};
 
 use bevy::{ecs::world::CommandQueue, prelude::*};
 
-use petgraph::graphmap::DiGraphMap;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::input::{MenuAction, MenuInput};
+use crate::autopilot::Autopilot;
+use crate::construction::ConstructionCosts;
+use crate::history::History;
+use crate::input::{MenuAction, MenuInput, MenuState};
+use crate::orders::Orders;
 use crate::railroad::{rail_tile_bundle, NetworkRoot, RailGraph, Track};
+use crate::replay::{
+    CommandLog, FixedTick, Replay, REPLAY_END_SLOT, REPLAY_LOG_FILE, REPLAY_START_SLOT,
+};
 use crate::sprites::SpriteAssets;
+use crate::stations::{station_bundle, Station};
+use crate::terrain::TerrainMap;
 use crate::trainbuilder::*;
 use crate::trains::*;
 
-const SAVEGAME_PATH: &str = "savegame/stupid.json";
-const CURRENT_SAVEGAME_VERSION: u32 = 6;
+pub mod check;
+mod format;
+mod migrations;
+mod slots;
+
+pub use format::SaveFormat;
+use slots::{slot_file, slot_path, SaveSlots, SlotRequest};
+pub use slots::{write_atomically, SAVEGAME_DIR};
+
+const CURRENT_SAVEGAME_VERSION: u32 = 14;
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 pub struct LoadSavePlugin;
 
 impl Plugin for LoadSavePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, initial_load_system)
+        app.init_resource::<SaveSlots>()
+            .insert_resource(AutosaveTimer(Timer::new(
+                AUTOSAVE_INTERVAL,
+                TimerMode::Repeating,
+            )))
+            .add_systems(PostStartup, initial_load_system)
+            .add_systems(OnEnter(MenuState::SaveMenu), slots::refresh_slots_system)
+            .add_systems(
+                Update,
+                slots::slot_menu_system.run_if(in_state(MenuState::SaveMenu)),
+            )
+            .add_systems(Update, autosave_timer_system)
             .add_systems(Last, save_system);
     }
 }
 
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
 /// This is a struct holding all the data that will get saved.
 ///
 /// Actual data is wrapped in [`SerDeserCell`] so no clones need to be performed.
@@ -32,14 +64,41 @@ struct SaveGame<'a> {
     version: u32,
     network: SerDeserCell<'a, RailGraph>,
     trains: Vec<SaveTrain<'a>>,
+    #[serde(default)]
+    stations: Vec<SerDeserCell<'a, Station>>,
+    #[serde(default)]
+    next_train_id: NextTrainId,
+    #[serde(default)]
+    speed_limit_mode: SpeedLimitMode,
+    /// The seed of the [`TerrainMap`], `None` for a map of only land.
+    #[serde(default)]
+    terrain_seed: Option<u64>,
+    #[serde(default)]
+    construction_costs: ConstructionCosts,
 }
 
 #[derive(Serialize, Deserialize)]
 /// contains the path and position of the whole train and all its wagons
 struct SaveTrain<'a> {
+    #[serde(default)]
+    id: TrainId,
     train: SerDeserCell<'a, Trail>,
     velocity: SerDeserCell<'a, Velocity>,
     wagons: Vec<SaveWagon<'a>>,
+    #[serde(default)]
+    orders: SerDeserCell<'a, Orders>,
+    #[serde(default)]
+    controller: SerDeserCell<'a, Controller>,
+    #[serde(default)]
+    autopilot: Option<Autopilot>,
+    /// Whether the train has a [`Crashed`] marker.
+    #[serde(default)]
+    crashed: bool,
+    /// Whether this is the [`PlayerControlledTrain`].
+    #[serde(default)]
+    player_controlled: bool,
+    #[serde(default = "default_train_name")]
+    name: String,
 }
 
 /// contains the components of a individual wagon (or locomotive)
@@ -47,6 +106,16 @@ struct SaveTrain<'a> {
 struct SaveWagon<'a> {
     wagon_type: SerDeserCell<'a, VehicleType>,
     stats: SerDeserCell<'a, VehicleStats>,
+    #[serde(default = "default_wagon_name")]
+    name: String,
+}
+
+fn default_train_name() -> String {
+    "Train".to_string()
+}
+
+fn default_wagon_name() -> String {
+    "Wagon".to_string()
 }
 
 /// This is a new game.
@@ -54,48 +123,72 @@ impl<'a> Default for SaveGame<'a> {
     fn default() -> Self {
         Self {
             version: CURRENT_SAVEGAME_VERSION,
-            network: SerDeserCell::Deser(RailGraph {
-                graph: DiGraphMap::new(),
-            }),
+            network: SerDeserCell::Deser(RailGraph::default()),
             trains: Vec::new(),
+            stations: Vec::new(),
+            next_train_id: NextTrainId::default(),
+            speed_limit_mode: SpeedLimitMode::default(),
+            terrain_seed: Some(rand::random()),
+            construction_costs: ConstructionCosts::default(),
         }
     }
 }
 impl<'a> SaveGame<'a> {
-    fn from_disk() -> Self {
-        fn load_savegame_file() -> Result<SaveGame<'static>, Box<dyn Error>> {
-            let savegame_data = fs::read_to_string(SAVEGAME_PATH)?;
-            let savegame: SaveGame = serde_json::from_str(savegame_data.as_str())?;
-            info!("Loaded savegame v{}", savegame.version);
-            // Todo: implement this, problem is, usually the from_str fails anyway, so this is unnesssery.
-            // if savegame.version != CURRENT_SAVEGAME_VERSION {
-            //     Err(format!(
-            //         "Savegame is not compatible: expected {}, was {}!",
-            //         CURRENT_SAVEGAME_VERSION, savegame.version
-            //     ))
-            // }
-            Ok(savegame)
-        }
-
-        load_savegame_file().unwrap_or_else(|err| {
-            info!("Creating new world, because: {}", err);
-            SaveGame::default()
-        })
+    /// Reads the slot `name` from disk in either format, upgrading it if it is from an
+    /// older version.
+    ///
+    /// Returns a new game if there is no such slot yet, but an error if it can't be loaded.
+    fn from_disk(name: &str) -> Result<Self, Box<dyn Error>> {
+        let Some((path, _)) = slot_file(name) else {
+            info!("Creating new world, because there is no savegame {name}");
+            return Ok(SaveGame::default());
+        };
+        let savegame: SaveGame = serde_json::from_value(read_json(&path)?)?;
+        info!("Loaded savegame v{}", savegame.version);
+        Ok(savegame)
     }
 
     fn from_world(world: &'a mut World) -> Self {
-        let mut trains_query = world.query::<(Entity, &Vehicles, &Trail, &Velocity)>();
-        let mut wagons_query = world.query::<(&TrainIndex, &VehicleType, &VehicleStats)>();
+        let mut trains_query = world.query::<(
+            &TrainId,
+            &Vehicles,
+            &Trail,
+            &Velocity,
+            &Orders,
+            &Controller,
+            Option<&Autopilot>,
+            Has<Crashed>,
+            Has<PlayerControlledTrain>,
+            Option<&Name>,
+        )>();
+        let mut wagons_query =
+            world.query::<(&TrainIndex, &VehicleType, &VehicleStats, Option<&Name>)>();
+        let mut stations_query = world.query::<&Station>();
 
         let mut trains = Vec::new();
-        for (_, vehicles, head, velocity) in trains_query.iter(world) {
+        for (
+            &id,
+            vehicles,
+            head,
+            velocity,
+            orders,
+            controller,
+            autopilot,
+            crashed,
+            player_controlled,
+            name,
+        ) in trains_query.iter(world)
+        {
             // Thanks a lot to https://stackoverflow.com/a/72605922/19331219
             let mut wagons = (0..vehicles.len()).map(|_| None).collect::<Vec<_>>();
             for child in vehicles.iter() {
-                if let Ok((unit_id, unit_type, unit_stats)) = wagons_query.get(world, child) {
+                if let Ok((unit_id, unit_type, unit_stats, unit_name)) =
+                    wagons_query.get(world, child)
+                {
                     let wagon = SaveWagon {
                         wagon_type: SerDeserCell::Ser(&unit_type),
                         stats: SerDeserCell::Ser(&unit_stats),
+                        name: unit_name.map_or_else(default_wagon_name, |n| n.to_string()),
                     };
                     wagons[unit_id.position as usize] = Some(wagon);
                 }
@@ -103,53 +196,218 @@ impl<'a> SaveGame<'a> {
             let wagons = wagons.into_iter().map(Option::unwrap).collect();
 
             let train = SaveTrain {
+                id,
                 train: SerDeserCell::Ser(&head),
                 velocity: SerDeserCell::Ser(&velocity),
                 wagons: wagons,
+                orders: SerDeserCell::Ser(&orders),
+                controller: SerDeserCell::Ser(&controller),
+                autopilot: autopilot.copied(),
+                crashed,
+                player_controlled,
+                name: name.map_or_else(default_train_name, |n| n.to_string()),
             };
             trains.push(train);
         }
 
+        let stations = stations_query.iter(world).map(SerDeserCell::Ser).collect();
+
         let graph = world.resource::<RailGraph>();
+        let next_train_id = world
+            .get_resource::<NextTrainId>()
+            .copied()
+            .unwrap_or_default();
+        let speed_limit_mode = world
+            .get_resource::<SpeedLimitMode>()
+            .copied()
+            .unwrap_or_default();
+        let terrain_seed = world
+            .get_resource::<TerrainMap>()
+            .and_then(TerrainMap::seed);
+        let construction_costs = world
+            .get_resource::<ConstructionCosts>()
+            .copied()
+            .unwrap_or_default();
         SaveGame {
             version: CURRENT_SAVEGAME_VERSION,
             network: SerDeserCell::Ser(&graph),
             trains: trains,
+            stations: stations,
+            next_train_id,
+            speed_limit_mode,
+            terrain_seed,
+            construction_costs,
         }
     }
 }
 
-/// System to listen to keypresses and load/save the game accordingly
+/// System to save and load the game, as requested by the save menu or the quick save keys,
+/// which use the current slot.
 fn save_system(world: &mut World) {
+    let mut slots = world.resource_mut::<SaveSlots>();
+    let mut request = slots.request.take();
+    let current = slots.current.clone();
+
     let mut query = world.query::<&MenuInput>();
-    let Ok(key_input) = query.single(world) else {
-        return;
-    };
-    let save = key_input.just_pressed(&MenuAction::Save);
-    let reload = key_input.just_pressed(&MenuAction::Reload);
-    let new_game = key_input.just_pressed(&MenuAction::NewGame);
-
-    if save {
-        save_game(world);
-    } else if reload {
-        clean_game(world);
-        load_game(world, SaveGame::from_disk());
-    } else if new_game {
-        clean_game(world);
-        load_game(world, SaveGame::default());
+    if let Ok(key_input) = query.single(world) {
+        if key_input.just_pressed(&MenuAction::Save) {
+            request = Some(SlotRequest::Save(current));
+        } else if key_input.just_pressed(&MenuAction::Reload) {
+            request = Some(SlotRequest::Load(current));
+        } else if key_input.just_pressed(&MenuAction::NewGame) {
+            request = Some(SlotRequest::NewGame);
+        } else if key_input.just_pressed(&MenuAction::ToggleRecording) {
+            request = Some(match *world.resource::<Replay>() {
+                Replay::Recording(_) => SlotRequest::StopRecording,
+                _ => SlotRequest::StartRecording,
+            });
+        }
+    }
+
+    match request {
+        Some(SlotRequest::Save(name)) => {
+            if let Err(err) = save_game(world, &name) {
+                error!("Couldn't save game to {name}: {err}");
+                return;
+            }
+            let in_menu = *world.resource::<State<MenuState>>().get() == MenuState::SaveMenu;
+            let mut slots = world.resource_mut::<SaveSlots>();
+            slots.current = name;
+            if in_menu {
+                slots.refresh();
+            }
+        }
+        Some(SlotRequest::Load(name)) => match SaveGame::from_disk(&name) {
+            Ok(savegame) => {
+                cancel_recording(world);
+                clean_game(world);
+                load_game(world, savegame);
+                world.resource_mut::<SaveSlots>().current = name;
+            }
+            Err(err) => error!("Couldn't load {name}, keeping the current world: {err}"),
+        },
+        Some(SlotRequest::NewGame) => {
+            cancel_recording(world);
+            clean_game(world);
+            load_game(world, SaveGame::default());
+        }
+        Some(SlotRequest::Autosave) => {
+            let name = slots::autosave_slot(1);
+            let result = slots::rotate_autosaves()
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|()| save_game(world, &name));
+            if let Err(err) = result {
+                error!("Autosave failed: {err}");
+            }
+        }
+        Some(SlotRequest::StartRecording) => {
+            // Continue from the loaded savegame, such that the world is the same as in a replay
+            let result = save_game(world, REPLAY_START_SLOT)
+                .and_then(|()| SaveGame::from_disk(REPLAY_START_SLOT));
+            match result {
+                Ok(savegame) => {
+                    clean_game(world);
+                    load_game(world, savegame);
+                    world.insert_resource(Replay::Recording(CommandLog::default()));
+                    info!("Started recording, press F9 again to stop");
+                }
+                Err(err) => error!("Couldn't start recording: {err}"),
+            }
+        }
+        Some(SlotRequest::StopRecording) => {
+            let Replay::Recording(mut log) = std::mem::take(&mut *world.resource_mut::<Replay>())
+            else {
+                return;
+            };
+            log.end_tick = *world.resource::<FixedTick>();
+            let result = log
+                .write(Path::new(REPLAY_LOG_FILE))
+                .and_then(|()| save_game(world, REPLAY_END_SLOT));
+            match result {
+                Ok(()) => info!(
+                    "Recorded {} commands in {} ticks to {REPLAY_LOG_FILE}",
+                    log.entries.len(),
+                    log.end_tick.0
+                ),
+                Err(err) => error!("Couldn't save recording: {err}"),
+            }
+        }
+        None => (),
     }
 }
 
+/// Stops a recording without saving it, since loading another game would break it.
+fn cancel_recording(world: &mut World) {
+    let mut replay = world.resource_mut::<Replay>();
+    if matches!(*replay, Replay::Recording(_)) {
+        warn!("Loading a game discards the recording");
+        *replay = Replay::Off;
+    }
+}
+
+/// System to request an autosave every [`AUTOSAVE_INTERVAL`].
+fn autosave_timer_system(
+    time: Res<Time<Real>>,
+    mut timer: ResMut<AutosaveTimer>,
+    mut slots: ResMut<SaveSlots>,
+) {
+    if timer.0.tick(time.delta()).just_finished() && slots.request.is_none() {
+        slots.request = Some(SlotRequest::Autosave);
+    }
+}
+
+/// Loads the default slot on startup. If it can't be loaded, the game exits instead of
+/// starting a new world, which would overwrite the savegame on the next save.
 fn initial_load_system(world: &mut World) {
-    load_game(world, SaveGame::from_disk());
+    let name = world.resource::<SaveSlots>().current.clone();
+    match SaveGame::from_disk(&name) {
+        Ok(savegame) => load_game(world, savegame),
+        Err(err) => {
+            error!("Couldn't load savegame {name}: {err}");
+            world.write_message(AppExit::error());
+        }
+    }
 }
 
-/// Helper to save the game
-fn save_game(world: &mut World) {
-    let savegame = SaveGame::from_world(world);
-    let savegame_data = serde_json::to_string(&savegame).expect("Couldn't serialize savegame");
-    fs::write(SAVEGAME_PATH, savegame_data).expect("Couldn't write to file");
-    info!("Saved game state");
+/// Loads the savegame at `path` in either format into `world`, replacing the current game,
+/// e.g. for the [`headless_app`](crate::headless_app).
+pub fn load_file(world: &mut World, path: &Path) -> Result<(), Box<dyn Error>> {
+    let savegame: SaveGame = serde_json::from_value(read_json(path)?)?;
+    clean_game(world);
+    load_game(world, savegame);
+    Ok(())
+}
+
+/// Saves the game in `world` to `path` in the given format.
+pub fn save_file(world: &mut World, path: &Path, format: SaveFormat) -> Result<(), Box<dyn Error>> {
+    let savegame_data = format::encode(to_json(world)?, format)?;
+    write_atomically(path, &savegame_data)?;
+    Ok(())
+}
+
+/// Returns the game in `world` as it would be saved, as JSON.
+pub fn to_json(world: &mut World) -> Result<Value, Box<dyn Error>> {
+    Ok(serde_json::to_value(SaveGame::from_world(world))?)
+}
+
+/// Reads the savegame at `path` in either format, upgraded to the current version, as JSON.
+pub fn read_json(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let (mut raw_savegame, _) = format::decode(&fs::read(path)?)?;
+    migrations::upgrade(&mut raw_savegame)?;
+    Ok(raw_savegame)
+}
+
+/// Helper to save the game into the slot `name`.
+///
+/// Existing slots keep their format, new ones use the default [`SaveFormat`].
+fn save_game(world: &mut World, name: &str) -> Result<(), Box<dyn Error>> {
+    let format = slot_file(name).map_or(SaveFormat::default(), |(_, format)| format);
+    let savegame = serde_json::to_value(SaveGame::from_world(world))?;
+    let savegame_data = format::encode(savegame, format)?;
+    fs::create_dir_all(SAVEGAME_DIR)?;
+    write_atomically(&slot_path(name, format), &savegame_data)?;
+    info!("Saved game state to {name}");
+    Ok(())
 }
 
 /// This helper function despawns relevant entities which constitute a savegame state
@@ -167,8 +425,20 @@ fn clean_game(world: &mut World) {
 }
 
 /// Helper to spawn the dynamic game state from a savegame. Requires the state to be cleaned first.
+///
+/// Without [`SpriteAssets`], e.g. in a headless app, the sprites get default images.
 fn load_game(world: &mut World, savegame: SaveGame) {
-    let assets = world.resource::<SpriteAssets>();
+    world.insert_resource(savegame.next_train_id);
+    world.insert_resource(savegame.speed_limit_mode);
+    world.insert_resource(TerrainMap::new(savegame.terrain_seed));
+    world.insert_resource(savegame.construction_costs);
+    world.insert_resource(FixedTick::default());
+    // Actions of the previous game can't be undone in this one
+    world.insert_resource(History::default());
+    let default_assets = SpriteAssets::default();
+    let assets = world
+        .get_resource::<SpriteAssets>()
+        .unwrap_or(&default_assets);
     let mut command_queue = CommandQueue::default();
     let mut commands = Commands::new(&mut command_queue, world);
 
@@ -183,24 +453,37 @@ fn load_game(world: &mut World, savegame: SaveGame) {
 
         let mut wagons = Vec::new();
         for (index, wagon) in train.wagons.into_iter().enumerate() {
-            wagons.push(spawn_wagon(
+            let id = spawn_wagon(
                 &mut commands,
                 assets,
                 wagon.wagon_type.get(),
                 wagon.stats.get(),
                 index as u16,
-            ));
+            );
+            commands.entity(id).insert(Name::new(wagon.name));
+            wagons.push(id);
         }
 
-        commands
-            .spawn(TrainBundle {
-                path: trail,
-                velocity: train.velocity.get(),
-                controller: Default::default(),
-                name: Name::new("Train"),
-                marker: TrainMarker,
-            })
-            .add_related::<VehicleOf>(&wagons);
+        let mut entity = commands.spawn(TrainBundle {
+            id: train.id,
+            path: trail,
+            velocity: train.velocity.get(),
+            controller: train.controller.get(),
+            protection: Protection::default(),
+            orders: train.orders.get(),
+            name: Name::new(train.name),
+            marker: TrainMarker,
+        });
+        entity.add_related::<VehicleOf>(&wagons);
+        if let Some(autopilot) = train.autopilot {
+            entity.insert(autopilot);
+        }
+        if train.crashed {
+            entity.insert(Crashed);
+        }
+        if train.player_controlled {
+            entity.insert(PlayerControlledTrain);
+        }
     }
 
     // Rails
@@ -222,6 +505,13 @@ fn load_game(world: &mut World, savegame: SaveGame) {
         }
     }
 
+    // Stations
+    for station in savegame.stations {
+        commands.entity(rail_root).with_children(|c| {
+            c.spawn(station_bundle(station.get()));
+        });
+    }
+
     command_queue.apply(world);
     world.insert_resource(network);
 }
@@ -248,6 +538,28 @@ impl<'a, T> SerDeserCell<'a, T> {
             SerDeserCell::Deser(x) => x,
         }
     }
+
+    fn inner(&self) -> &T {
+        match self {
+            SerDeserCell::Ser(x) => x,
+            SerDeserCell::Deser(x) => x,
+        }
+    }
+
+    /// Returns `None` for borrowed data, which can't be changed.
+    fn inner_mut(&mut self) -> Option<&mut T> {
+        match self {
+            SerDeserCell::Ser(_) => None,
+            SerDeserCell::Deser(x) => Some(x),
+        }
+    }
+}
+
+/// Allows `#[serde(default)]` for fields added in newer savegame versions.
+impl<'a, T: Default> Default for SerDeserCell<'a, T> {
+    fn default() -> Self {
+        Self::Deser(T::default())
+    }
 }
 
 impl<'a, T> Serialize for SerDeserCell<'a, T>
@@ -276,3 +588,246 @@ where
         Ok(Self::Deser(Deserialize::deserialize(deserializer)?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::orders::{Order, Stop};
+    use crate::railroad::TrackType;
+    use crate::tilemap::Joint;
+
+    const STATS: VehicleStats = VehicleStats {
+        weight: 80.0,
+        acceleration_force: 250.0,
+        braking_force: 400.0,
+    };
+
+    fn empty_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(SpriteAssets::default());
+        world.insert_resource(RailGraph::default());
+        world.init_resource::<NextTrainId>();
+        world
+    }
+
+    /// A world with two straight tracks and a crashed, player controlled train on them.
+    fn example_world() -> World {
+        let mut world = empty_world();
+        let start = Joint::default();
+        let mut rail_graph = RailGraph::default();
+        rail_graph.add_double_track(Track {
+            joint: start,
+            heading: TrackType::Straight,
+        });
+        rail_graph.add_double_track(Track {
+            joint: start.next_straight(),
+            heading: TrackType::Straight,
+        });
+        world.insert_resource(rail_graph);
+        world.insert_resource(TerrainMap::new(Some(42)));
+
+        let mut command_queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut command_queue, &world);
+        let assets = world.resource::<SpriteAssets>();
+        let wagons = [
+            (VehicleType::Locomotive, "Locomotive"),
+            (VehicleType::Wagon, "Boxcar"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (wagon_type, name))| {
+            let id = spawn_wagon(&mut commands, assets, wagon_type, STATS, index as u16);
+            commands.entity(id).insert(Name::new(name));
+            id
+        })
+        .collect::<Vec<_>>();
+
+        let trail = Trail {
+            path: vec![
+                start,
+                start.next_straight(),
+                start.next_straight().next_straight(),
+            ],
+            path_progress: 2.0,
+            length: 2,
+        };
+        let mut bundle = TrainBundle::new(trail, 30.0);
+        bundle.velocity.velocity = 12.5;
+        bundle.controller = Controller {
+            throttle: 0.25,
+            brake: 0.75,
+        };
+        bundle.orders.orders = vec![
+            Order::GoTo {
+                stop: Stop::Joint(start),
+                arrival: Some(42.0),
+            },
+            Order::Wait { seconds: 5.0 },
+        ];
+        bundle.orders.repeat = true;
+        bundle.name = Name::new("Express");
+        commands
+            .spawn(bundle)
+            .insert((
+                Crashed,
+                PlayerControlledTrain,
+                Autopilot { destination: start },
+            ))
+            .add_related::<VehicleOf>(&wagons);
+
+        command_queue.apply(&mut world);
+        world
+    }
+
+    /// Saves `world` in `format` and loads it into a new world.
+    fn round_trip(world: &mut World, format: SaveFormat) -> World {
+        let savegame = serde_json::to_value(SaveGame::from_world(world)).unwrap();
+        let data = format::encode(savegame, format).unwrap();
+        let (savegame, decoded_format) = format::decode(&data).unwrap();
+        assert_eq!(decoded_format, format);
+
+        let mut loaded = empty_world();
+        load_game(&mut loaded, serde_json::from_value(savegame).unwrap());
+        loaded
+    }
+
+    fn assert_same_trains(expected: &mut World, actual: &mut World) {
+        let trains = |world: &mut World| {
+            let mut query = world.query::<(
+                &TrainId,
+                &Trail,
+                &Velocity,
+                &Controller,
+                &Orders,
+                Option<&Autopilot>,
+                Has<Crashed>,
+                Has<PlayerControlledTrain>,
+                &Name,
+                &Vehicles,
+            )>();
+            let mut wagons_query = world.query::<(&TrainIndex, &VehicleType, &Name)>();
+            query
+                .iter(world)
+                .map(
+                    |(
+                        id,
+                        trail,
+                        velocity,
+                        controller,
+                        orders,
+                        autopilot,
+                        crashed,
+                        player,
+                        name,
+                        vehicles,
+                    )| {
+                        let mut wagons = vehicles
+                            .iter()
+                            .map(|id| {
+                                let (index, wagon_type, name) =
+                                    wagons_query.get(world, id).unwrap();
+                                (index.position, *wagon_type, name.to_string())
+                            })
+                            .collect::<Vec<_>>();
+                        wagons.sort_by_key(|(index, _, _)| *index);
+                        (
+                            *id,
+                            (trail.path.clone(), trail.path_progress, trail.length),
+                            velocity.velocity,
+                            *controller,
+                            (orders.orders.clone(), orders.current, orders.repeat),
+                            autopilot.copied(),
+                            (crashed, player),
+                            name.to_string(),
+                            wagons,
+                        )
+                    },
+                )
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            expected.resource::<NextTrainId>(),
+            actual.resource::<NextTrainId>()
+        );
+        let expected = trains(expected);
+        assert_eq!(expected.len(), 1);
+        assert_eq!(expected[0].0, TrainId(1));
+        assert_eq!(expected, trains(actual));
+    }
+
+    #[test]
+    fn round_trip_json() {
+        let mut world = example_world();
+        let mut loaded = round_trip(&mut world, SaveFormat::Json);
+        assert_same_trains(&mut world, &mut loaded);
+        assert_eq!(
+            world.resource::<RailGraph>().graph.edge_count(),
+            loaded.resource::<RailGraph>().graph.edge_count()
+        );
+        let terrain = |world: &World| {
+            let terrain_map = world.resource::<TerrainMap>();
+            let mut tiles = terrain_map.iter().collect::<Vec<_>>();
+            tiles.sort_by_key(|(tile, _)| *tile);
+            (terrain_map.seed(), tiles)
+        };
+        assert_eq!(terrain(&world), terrain(&loaded));
+    }
+
+    #[test]
+    fn round_trip_binary() {
+        let mut world = example_world();
+        let mut loaded = round_trip(&mut world, SaveFormat::Binary);
+        assert_same_trains(&mut world, &mut loaded);
+    }
+
+    #[test]
+    fn round_trip_twice_is_stable() {
+        let mut world = example_world();
+        let mut loaded = round_trip(&mut world, SaveFormat::Json);
+        let mut reloaded = round_trip(&mut loaded, SaveFormat::Json);
+        assert_same_trains(&mut world, &mut reloaded);
+    }
+
+    #[test]
+    fn upgraded_savegame_has_default_components() {
+        let joint = |x: i32| json!({ "tile": [x, 0], "side": 0 });
+        let mut savegame = json!({
+            "version": 10,
+            "network": serde_json::to_value(RailGraph::default()).unwrap(),
+            "trains": [{
+                "train": { "path": [joint(0), joint(1)], "path_progress": 1.0, "length": 1 },
+                "velocity": { "velocity": 3.0, "max_velocity": 30.0 },
+                "wagons": [{ "wagon_type": "Locomotive", "stats": serde_json::to_value(&STATS).unwrap() }],
+                "orders": { "orders": [], "current": 0, "repeat": false, "elapsed": 0.0,
+                            "order_elapsed": 0.0, "delay": null },
+            }],
+            "stations": [],
+        });
+        migrations::upgrade(&mut savegame).unwrap();
+        assert_eq!(savegame["version"], json!(CURRENT_SAVEGAME_VERSION));
+
+        let mut world = empty_world();
+        load_game(&mut world, serde_json::from_value(savegame).unwrap());
+        let mut query = world.query::<(
+            &TrainId,
+            &Controller,
+            Option<&Autopilot>,
+            Has<Crashed>,
+            Has<PlayerControlledTrain>,
+            &Name,
+        )>();
+        let (&id, controller, autopilot, crashed, player, name) = query.single(&world).unwrap();
+        assert_eq!(id, TrainId(1));
+        assert_eq!(*world.resource::<NextTrainId>(), NextTrainId(2));
+        assert_eq!(*controller, Controller::default());
+        assert_eq!(autopilot, None);
+        assert!(!crashed && !player);
+        assert_eq!(name.as_str(), "Train");
+        let mut wagons = world.query::<(&VehicleType, &Name)>();
+        let (wagon_type, name) = wagons.single(&world).unwrap();
+        assert_eq!(*wagon_type, VehicleType::Locomotive);
+        assert_eq!(name.as_str(), "Wagon");
+    }
+}
@@ -0,0 +1,436 @@
+//! Inspecting, validating and repairing savegame files outside of the game,
+//! used by the `hexrails-save` binary.
+
+use std::{error::Error, fmt, fs, path::Path};
+
+use petgraph::EdgeDirection;
+
+use super::format::{self, SaveFormat};
+use super::migrations;
+use super::slots::write_atomically;
+use super::SaveGame;
+use crate::railroad::{RailGraph, Track};
+use crate::tilemap::Joint;
+
+/// A savegame file, upgraded to the current version.
+pub struct SaveFile {
+    /// The format the file was stored in.
+    pub format: SaveFormat,
+    /// The version of the file, before it was upgraded.
+    pub version: u32,
+    savegame: SaveGame<'static>,
+}
+
+/// A broken invariant of a savegame, most of them would make the game misbehave or panic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// An edge of the rail graph whose joints are not connected by a track.
+    NotATrack { from: Joint, to: Joint },
+    /// An edge `from -> to` without the edge `to.opposite() -> from.opposite()`.
+    MissingOpposite { from: Joint, to: Joint },
+    /// The trail of the train with this index breaks
+    /// [`check_invariant`](crate::trains::Trail::check_invariant).
+    BrokenTrail { train: usize },
+    /// The train with this index stands on an edge which is not in the rail graph.
+    OffTrack { train: usize },
+    /// The number of wagons of the train with this index differs from its trail's length.
+    WagonCount {
+        train: usize,
+        wagons: usize,
+        length: u16,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NotATrack { from, to } => write!(f, "edge {from:?} -> {to:?} is not a track"),
+            Problem::MissingOpposite { from, to } => {
+                write!(f, "edge {from:?} -> {to:?} has no opposite edge")
+            }
+            Problem::BrokenTrail { train } => write!(f, "train {train} has a broken trail"),
+            Problem::OffTrack { train } => write!(f, "train {train} is not on the tracks"),
+            Problem::WagonCount {
+                train,
+                wagons,
+                length,
+            } => write!(
+                f,
+                "train {train} has {wagons} wagons, but a length of {length}"
+            ),
+        }
+    }
+}
+
+impl Problem {
+    /// How [`SaveFile::repair`] fixes this problem.
+    pub fn repair_description(&self) -> &'static str {
+        match self {
+            Problem::NotATrack { .. } => "removed the edge",
+            Problem::MissingOpposite { .. } => "added the opposite edge",
+            Problem::BrokenTrail { .. } | Problem::OffTrack { .. } | Problem::WagonCount { .. } => {
+                "removed the train"
+            }
+        }
+    }
+
+    /// The index of the train with this problem, if it is one of a train.
+    pub fn train(&self) -> Option<usize> {
+        match *self {
+            Problem::NotATrack { .. } | Problem::MissingOpposite { .. } => None,
+            Problem::BrokenTrail { train }
+            | Problem::OffTrack { train }
+            | Problem::WagonCount { train, .. } => Some(train),
+        }
+    }
+}
+
+impl SaveFile {
+    /// Reads and upgrades the savegame at `path`, in either format.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (mut raw_savegame, format) = format::decode(&fs::read(path)?)?;
+        let version = raw_savegame["version"]
+            .as_u64()
+            .ok_or("Savegame has no version")? as u32;
+        migrations::upgrade(&mut raw_savegame)?;
+        Ok(Self {
+            format,
+            version,
+            savegame: serde_json::from_value(raw_savegame)?,
+        })
+    }
+
+    /// Writes the savegame to `path` in `format`, at the current version.
+    pub fn write(&self, path: &Path, format: SaveFormat) -> Result<(), Box<dyn Error>> {
+        let data = format::encode(serde_json::to_value(&self.savegame)?, format)?;
+        write_atomically(path, &data)?;
+        Ok(())
+    }
+
+    /// The upgraded savegame as indented JSON.
+    pub fn to_pretty_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(&self.savegame)?)
+    }
+
+    pub fn rail_graph(&self) -> &RailGraph {
+        self.savegame.network.inner()
+    }
+
+    pub fn train_count(&self) -> usize {
+        self.savegame.trains.len()
+    }
+
+    pub fn station_count(&self) -> usize {
+        self.savegame.stations.len()
+    }
+
+    /// Returns all broken invariants, see [`Problem`].
+    pub fn check(&self) -> Vec<Problem> {
+        let graph = &self.rail_graph().graph;
+        let mut problems = Vec::new();
+        for (from, to, _) in graph.all_edges() {
+            if Track::from_joints(from, to).is_none() {
+                problems.push(Problem::NotATrack { from, to });
+            } else if !graph.contains_edge(to.opposite(), from.opposite()) {
+                problems.push(Problem::MissingOpposite { from, to });
+            }
+        }
+
+        for (index, train) in self.savegame.trains.iter().enumerate() {
+            let trail = train.train.inner();
+            if !trail.check_invariant() {
+                problems.push(Problem::BrokenTrail { train: index });
+            } else if !trail
+                .trim()
+                .windows(2)
+                .all(|edge| graph.contains_edge(edge[0], edge[1]))
+            {
+                problems.push(Problem::OffTrack { train: index });
+            } else if train.wagons.len() != trail.length as usize {
+                problems.push(Problem::WagonCount {
+                    train: index,
+                    wagons: train.wagons.len(),
+                    length: trail.length,
+                });
+            }
+        }
+        problems
+    }
+
+    /// Fixes all problems found by [`SaveFile::check`], returning them.
+    ///
+    /// The rail graph is repaired first, then the trains are checked again, since removing
+    /// edges may leave trains off the tracks.
+    pub fn repair(&mut self) -> Result<Vec<Problem>, Box<dyn Error>> {
+        let mut problems = self.check();
+        let rail_graph = self
+            .savegame
+            .network
+            .inner_mut()
+            .ok_or("The rail network is borrowed and can't be repaired")?;
+        for problem in &problems {
+            match *problem {
+                Problem::NotATrack { from, to } => {
+                    rail_graph.graph.remove_edge(from, to);
+                    for joint in [from, to] {
+                        let is_orphaned = rail_graph
+                            .graph
+                            .neighbors_directed(joint, EdgeDirection::Outgoing)
+                            .chain(
+                                rail_graph
+                                    .graph
+                                    .neighbors_directed(joint, EdgeDirection::Incoming),
+                            )
+                            .next()
+                            .is_none();
+                        if is_orphaned {
+                            rail_graph.graph.remove_node(joint);
+                            rail_graph.signals.remove(&joint);
+                            rail_graph.switches.remove(&joint);
+                        }
+                    }
+                }
+                Problem::MissingOpposite { from, to } => {
+                    let properties = rail_graph.graph[(from, to)].clone();
+                    rail_graph
+                        .graph
+                        .add_edge(to.opposite(), from.opposite(), properties);
+                }
+                Problem::BrokenTrail { .. }
+                | Problem::OffTrack { .. }
+                | Problem::WagonCount { .. } => {}
+            }
+        }
+
+        problems.retain(|problem| problem.train().is_none());
+        let train_problems: Vec<Problem> = self
+            .check()
+            .into_iter()
+            .filter(|problem| problem.train().is_some())
+            .collect();
+        // Back to front, so the indices stay valid
+        for train in train_problems.iter().rev().filter_map(Problem::train) {
+            self.savegame.trains.remove(train);
+        }
+        problems.extend(train_problems);
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::railroad::{TrackProperties, TrackType};
+    use crate::savegame::{SaveTrain, SaveWagon, SerDeserCell, CURRENT_SAVEGAME_VERSION};
+    use crate::tilemap::{Direction, Tile};
+    use crate::trains::{Trail, TrainId, VehicleStats, VehicleType, Velocity};
+
+    fn start() -> Joint {
+        Joint {
+            tile: Tile(0, 0),
+            side: Direction::EAST,
+        }
+    }
+
+    /// Two straight tracks from [`start`].
+    fn rail_graph() -> RailGraph {
+        let mut rail_graph = RailGraph::default();
+        for joint in [start(), start().next_straight()] {
+            rail_graph.add_double_track(Track {
+                joint,
+                heading: TrackType::Straight,
+            });
+        }
+        rail_graph
+    }
+
+    /// A train on the tracks of [`rail_graph`], whose front is at `path_progress`.
+    fn train(path_progress: f32, length: u16, wagons: usize) -> SaveTrain<'static> {
+        let joints = [start(), start().next_straight()];
+        let wagon = || SaveWagon {
+            wagon_type: SerDeserCell::Deser(VehicleType::Wagon),
+            stats: SerDeserCell::Deser(VehicleStats::default_for_type(VehicleType::Wagon)),
+            name: "Wagon".to_string(),
+        };
+        SaveTrain {
+            id: TrainId(1),
+            train: SerDeserCell::Deser(Trail {
+                path: vec![joints[0], joints[1], joints[1].next_straight()],
+                path_progress,
+                length,
+            }),
+            velocity: SerDeserCell::Deser(Velocity {
+                velocity: 0.0,
+                max_velocity: 30.0,
+            }),
+            wagons: (0..wagons).map(|_| wagon()).collect(),
+            orders: Default::default(),
+            controller: Default::default(),
+            autopilot: None,
+            crashed: false,
+            player_controlled: false,
+            name: "Train".to_string(),
+        }
+    }
+
+    fn save_file(rail_graph: RailGraph, trains: Vec<SaveTrain<'static>>) -> SaveFile {
+        SaveFile {
+            format: SaveFormat::Json,
+            version: CURRENT_SAVEGAME_VERSION,
+            savegame: SaveGame {
+                network: SerDeserCell::Deser(rail_graph),
+                trains,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn valid_savegame_has_no_problems() {
+        let mut file = save_file(rail_graph(), vec![train(2.0, 1, 1)]);
+        assert_eq!(file.check(), Vec::new());
+        assert_eq!(file.repair().unwrap(), Vec::new());
+        assert_eq!(file.train_count(), 1);
+    }
+
+    #[test]
+    fn edges_which_are_not_tracks() {
+        let mut rail_graph = rail_graph();
+        let far = Joint {
+            tile: Tile(5, 5),
+            side: Direction::WEST,
+        };
+        rail_graph
+            .graph
+            .add_edge(start(), far, TrackProperties::default());
+        let mut file = save_file(rail_graph, Vec::new());
+        let problem = Problem::NotATrack {
+            from: start(),
+            to: far,
+        };
+        assert_eq!(file.check(), vec![problem.clone()]);
+
+        assert_eq!(file.repair().unwrap(), vec![problem]);
+        assert_eq!(file.check(), Vec::new());
+        let graph = &file.rail_graph().graph;
+        assert!(!graph.contains_edge(start(), far));
+        // The orphaned joint is removed, the one still used by tracks is kept
+        assert!(!graph.contains_node(far));
+        assert!(graph.contains_node(start()));
+    }
+
+    #[test]
+    fn edges_without_opposite() {
+        let mut rail_graph = rail_graph();
+        let track = Track {
+            joint: start(),
+            heading: TrackType::CurvedLeft,
+        };
+        rail_graph.graph.add_edge(
+            track.joint,
+            track.end_joint(),
+            TrackProperties {
+                speed_limit: Some(10.0),
+            },
+        );
+        let mut file = save_file(rail_graph, Vec::new());
+        let problem = Problem::MissingOpposite {
+            from: track.joint,
+            to: track.end_joint(),
+        };
+        assert_eq!(file.check(), vec![problem.clone()]);
+
+        assert_eq!(file.repair().unwrap(), vec![problem]);
+        assert_eq!(file.check(), Vec::new());
+        let reversed = track.reversed();
+        assert_eq!(
+            file.rail_graph()
+                .speed_limit(reversed.joint, reversed.end_joint()),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn broken_trails() {
+        // The front is beyond the end of the path
+        let file = save_file(rail_graph(), vec![train(1.0, 1, 1), train(3.5, 1, 1)]);
+        assert_eq!(file.check(), vec![Problem::BrokenTrail { train: 1 }]);
+    }
+
+    #[test]
+    fn wrong_wagon_counts() {
+        let file = save_file(rail_graph(), vec![train(2.0, 1, 2)]);
+        assert_eq!(
+            file.check(),
+            vec![Problem::WagonCount {
+                train: 0,
+                wagons: 2,
+                length: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn repair_removes_broken_trains() {
+        let trains = vec![
+            train(3.5, 1, 1),
+            train(2.0, 1, 1),
+            train(2.0, 2, 2),
+            train(2.0, 1, 0),
+        ];
+        let mut file = save_file(rail_graph(), trains);
+        let problems = file.repair().unwrap();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(file.train_count(), 2);
+        assert_eq!(file.check(), Vec::new());
+        let lengths: Vec<u16> = file
+            .savegame
+            .trains
+            .iter()
+            .map(|train| train.train.inner().length)
+            .collect();
+        assert_eq!(lengths, vec![1, 2]);
+    }
+    #[test]
+    fn trains_off_the_tracks() {
+        let mut rail_graph = rail_graph();
+        rail_graph.remove_double_track(Track {
+            joint: start().next_straight(),
+            heading: TrackType::Straight,
+        });
+        let file = save_file(rail_graph, vec![train(1.0, 1, 1), train(2.0, 1, 1)]);
+        assert_eq!(file.check(), vec![Problem::OffTrack { train: 1 }]);
+    }
+
+    #[test]
+    fn repair_removes_trains_on_removed_edges() {
+        let mut rail_graph = rail_graph();
+        let far = Joint {
+            tile: Tile(5, 5),
+            side: Direction::WEST,
+        };
+        rail_graph
+            .graph
+            .add_edge(start(), far, TrackProperties::default());
+        let mut on_edge = train(1.0, 1, 1);
+        on_edge.train = SerDeserCell::Deser(Trail {
+            path: vec![start(), far],
+            path_progress: 1.0,
+            length: 1,
+        });
+        let mut file = save_file(rail_graph, vec![train(2.0, 1, 1), on_edge]);
+        let not_a_track = Problem::NotATrack {
+            from: start(),
+            to: far,
+        };
+        // The train is fine until its edge is removed
+        assert_eq!(file.check(), vec![not_a_track.clone()]);
+
+        assert_eq!(
+            file.repair().unwrap(),
+            vec![not_a_track, Problem::OffTrack { train: 1 }]
+        );
+        assert_eq!(file.train_count(), 1);
+        assert_eq!(file.check(), Vec::new());
+    }
+}
@@ -0,0 +1,177 @@
+//! The file formats of savegames: JSON for debugging and a compact binary format.
+//!
+//! The binary format is the [`MAGIC`] header followed by the savegame as MessagePack,
+//! where [`Joint`](crate::tilemap::Joint)s are stored as plain integers instead of objects,
+//! and lists of joints (e.g. the paths of trains) are delta coded, since consecutive
+//! joints are usually neighbors.
+//!
+//! Both formats are converted from and to a [`serde_json::Value`], such that the
+//! [migrations](super::migrations) work on either.
+
+use std::{error::Error, path::Path};
+
+use serde_json::{json, Map, Value};
+
+/// The first bytes of a binary savegame. JSON savegames start with `{` instead.
+const MAGIC: &[u8; 4] = b"HXRB";
+/// Replaces a single joint object, the value is `[x, y, side]`.
+const JOINT_KEY: &str = "$joint";
+/// Replaces a list of joints, the value is `[x, y, side, dx, dy, dside, ...]`,
+/// where every joint is given as difference to the previous one.
+const JOINTS_KEY: &str = "$joints";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaveFormat {
+    Json,
+    #[default]
+    Binary,
+}
+
+impl SaveFormat {
+    pub const ALL: [SaveFormat; 2] = [SaveFormat::Binary, SaveFormat::Json];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SaveFormat::Json => "json",
+            SaveFormat::Binary => "hxrb",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+
+    /// The format of a file at `path`, according to its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            SaveFormat::Json => SaveFormat::Binary,
+            SaveFormat::Binary => SaveFormat::Json,
+        }
+    }
+}
+
+/// Encodes a savegame in the given format.
+pub fn encode(savegame: Value, format: SaveFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+    match format {
+        SaveFormat::Json => Ok(serde_json::to_vec(&savegame)?),
+        SaveFormat::Binary => {
+            let mut data = MAGIC.to_vec();
+            rmp_serde::encode::write(&mut data, &compact(savegame))?;
+            Ok(data)
+        }
+    }
+}
+
+/// Decodes a savegame in either format, which is detected by the [`MAGIC`] header.
+pub fn decode(data: &[u8]) -> Result<(Value, SaveFormat), Box<dyn Error>> {
+    match data.strip_prefix(MAGIC) {
+        Some(binary) => {
+            let compacted: Value = rmp_serde::from_slice(binary)?;
+            Ok((expand(compacted)?, SaveFormat::Binary))
+        }
+        None => Ok((serde_json::from_slice(data)?, SaveFormat::Json)),
+    }
+}
+
+/// Converts a savegame from either format to `format`, without upgrading it.
+pub fn convert(data: &[u8], format: SaveFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (savegame, _) = decode(data)?;
+    encode(savegame, format)
+}
+
+/// Returns the joint as `[x, y, side]`, if `value` is a serialized joint.
+fn as_joint(value: &Value) -> Option<[i64; 3]> {
+    let object = value.as_object()?;
+    if object.len() != 2 {
+        return None;
+    }
+    let side = object.get("side")?.as_i64()?;
+    match object.get("tile")?.as_array()?.as_slice() {
+        [x, y] => Some([x.as_i64()?, y.as_i64()?, side]),
+        _ => None,
+    }
+}
+
+fn is_joint(value: &Value) -> bool {
+    as_joint(value).is_some()
+}
+
+fn joint_value([x, y, side]: [i64; 3]) -> Value {
+    json!({ "tile": [x, y], "side": side })
+}
+
+/// Replaces all joints in `value` with their compact representation.
+fn compact(value: Value) -> Value {
+    if let Some(joint) = as_joint(&value) {
+        return json!({ JOINT_KEY: joint });
+    }
+    match value {
+        Value::Array(items) if !items.is_empty() && items.iter().all(is_joint) => {
+            let mut previous = [0; 3];
+            let mut deltas = Vec::with_capacity(3 * items.len());
+            for joint in items.iter().filter_map(as_joint) {
+                deltas.extend((0..3).map(|i| joint[i] - previous[i]));
+                previous = joint;
+            }
+            json!({ JOINTS_KEY: deltas })
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(compact).collect()),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| (key, compact(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Inverse of [`compact`].
+fn expand(value: Value) -> Result<Value, Box<dyn Error>> {
+    let integers = |value: &Value| -> Result<Vec<i64>, Box<dyn Error>> {
+        value
+            .as_array()
+            .and_then(|items| items.iter().map(Value::as_i64).collect())
+            .ok_or_else(|| format!("Expected a list of integers, got {value}").into())
+    };
+
+    match value {
+        Value::Object(object) if object.len() == 1 && object.contains_key(JOINT_KEY) => {
+            match integers(&object[JOINT_KEY])?.as_slice() {
+                &[x, y, side] => Ok(joint_value([x, y, side])),
+                other => Err(format!("Expected a joint, got {other:?}").into()),
+            }
+        }
+        Value::Object(object) if object.len() == 1 && object.contains_key(JOINTS_KEY) => {
+            let deltas = integers(&object[JOINTS_KEY])?;
+            if deltas.len() % 3 != 0 {
+                return Err(format!("Expected a list of joints, got {deltas:?}").into());
+            }
+            let mut previous = [0; 3];
+            let joints = deltas
+                .chunks_exact(3)
+                .map(|delta| {
+                    previous = [0, 1, 2].map(|i| previous[i] + delta[i]);
+                    joint_value(previous)
+                })
+                .collect();
+            Ok(Value::Array(joints))
+        }
+        Value::Object(object) => Ok(Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| Ok((key, expand(value)?)))
+                .collect::<Result<Map<_, _>, Box<dyn Error>>>()?,
+        )),
+        Value::Array(items) => Ok(Value::Array(
+            items.into_iter().map(expand).collect::<Result<_, _>>()?,
+        )),
+        other => Ok(other),
+    }
+}
@@ -0,0 +1,159 @@
+//! Upgrades for savegames of older versions.
+//!
+//! The migrations work on the raw JSON, before it is deserialized into a [`SaveGame`](super::SaveGame),
+//! so they can handle any change of the format. Every migration upgrades by exactly one version.
+
+use std::error::Error;
+
+use bevy::prelude::*;
+use serde_json::{json, Map, Value};
+
+use super::CURRENT_SAVEGAME_VERSION;
+
+type Migration = fn(&mut Value) -> Result<(), Box<dyn Error>>;
+
+/// The oldest version that can still be loaded.
+const OLDEST_SUPPORTED_VERSION: u32 = 6;
+
+/// `MIGRATIONS[i]` upgrades from version `OLDEST_SUPPORTED_VERSION + i` to the next one.
+const MIGRATIONS: &[Migration] = &[
+    v6_to_v7, v7_to_v8, v8_to_v9, v9_to_v10, v10_to_v11, v11_to_v12, v12_to_v13, v13_to_v14,
+];
+
+// Adding a new savegame version requires adding a migration aswell.
+const _: () = assert!(
+    OLDEST_SUPPORTED_VERSION as usize + MIGRATIONS.len() == CURRENT_SAVEGAME_VERSION as usize
+);
+
+/// Upgrades `savegame` to [`CURRENT_SAVEGAME_VERSION`] by applying all migrations in order.
+///
+/// Fails if the version is missing, too old or newer than the current one.
+pub fn upgrade(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    let version = savegame
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or("Savegame has no version")? as u32;
+    if version > CURRENT_SAVEGAME_VERSION {
+        return Err(format!(
+            "Savegame v{version} is from the future, this game supports up to v{CURRENT_SAVEGAME_VERSION}"
+        )
+        .into());
+    }
+    if version < OLDEST_SUPPORTED_VERSION {
+        return Err(format!(
+            "Savegame v{version} is too old, the oldest supported version is v{OLDEST_SUPPORTED_VERSION}"
+        )
+        .into());
+    }
+
+    let pending = &MIGRATIONS[(version - OLDEST_SUPPORTED_VERSION) as usize..];
+    for (from, migration) in (version..).zip(pending) {
+        migration(savegame).map_err(|err| format!("Upgrading savegame v{from} failed: {err}"))?;
+        savegame["version"] = json!(from + 1);
+        info!("Upgraded savegame from v{from} to v{}", from + 1);
+    }
+    Ok(())
+}
+
+/// Helper to get the field `key` of `value`, which must be a JSON object.
+fn field_mut<'a>(value: &'a mut Value, key: &str) -> Result<&'a mut Value, Box<dyn Error>> {
+    value
+        .get_mut(key)
+        .ok_or_else(|| format!("Missing field `{key}`").into())
+}
+
+/// Helper to get `value` as a JSON object.
+fn object_mut(value: &mut Value) -> Result<&mut Map<String, Value>, Box<dyn Error>> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| format!("Expected an object, got {value}").into())
+}
+
+/// Helper to get the entries of `value`, which must be a JSON array.
+fn entries_mut(value: &mut Value) -> Result<&mut Vec<Value>, Box<dyn Error>> {
+    value
+        .as_array_mut()
+        .ok_or_else(|| "Expected an array".into())
+}
+
+/// Adds the switch settings and signals to the rail network.
+fn v6_to_v7(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    let network = object_mut(field_mut(savegame, "network")?)?;
+    network.insert("switches".into(), json!([]));
+    network.insert("signals".into(), json!([]));
+    Ok(())
+}
+
+/// Adds stations.
+fn v7_to_v8(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    savegame["stations"] = json!([]);
+    Ok(())
+}
+
+/// Adds the orders of every train.
+fn v8_to_v9(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    for train in entries_mut(field_mut(savegame, "trains")?)? {
+        object_mut(train)?.insert(
+            "orders".into(),
+            json!({
+                "orders": [],
+                "current": 0,
+                "repeat": false,
+                "elapsed": 0.0,
+                "order_elapsed": 0.0,
+                "delay": null,
+            }),
+        );
+    }
+    Ok(())
+}
+
+/// Adds speed limits to tracks. They are optional, so tracks without one keep the default.
+fn v9_to_v10(_savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+/// Adds the remaining components of trains and vehicles, which were reset on every load.
+fn v10_to_v11(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    for train in entries_mut(field_mut(savegame, "trains")?)? {
+        for wagon in entries_mut(field_mut(train, "wagons")?)? {
+            object_mut(wagon)?.insert("name".into(), json!("Wagon"));
+        }
+        object_mut(field_mut(train, "orders")?)?.insert("dispatched".into(), Value::Null);
+        let train = object_mut(train)?;
+        train.insert(
+            "controller".into(),
+            json!({ "throttle": 0.0, "brake": 0.0 }),
+        );
+        train.insert("autopilot".into(), Value::Null);
+        train.insert("crashed".into(), json!(false));
+        train.insert("player_controlled".into(), json!(false));
+        train.insert("name".into(), json!("Train"));
+    }
+    Ok(())
+}
+
+/// Adds the ids of trains, numbered in the order they are saved, and the speed limit mode.
+fn v11_to_v12(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    let trains = entries_mut(field_mut(savegame, "trains")?)?;
+    for (index, train) in trains.iter_mut().enumerate() {
+        object_mut(train)?.insert("id".into(), json!(index + 1));
+    }
+    let next_train_id = trains.len() + 1;
+    let savegame = object_mut(savegame)?;
+    savegame.insert("next_train_id".into(), json!(next_train_id));
+    savegame.insert("speed_limit_mode".into(), json!("Clamp"));
+    Ok(())
+}
+
+/// Adds the seed of the terrain. Older maps were built on land only, so they stay that way.
+fn v12_to_v13(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    object_mut(savegame)?.insert("terrain_seed".into(), Value::Null);
+    Ok(())
+}
+
+/// Adds the costs of the tracks built so far, which weren't tracked before.
+fn v13_to_v14(savegame: &mut Value) -> Result<(), Box<dyn Error>> {
+    object_mut(savegame)?.insert("construction_costs".into(), json!(0));
+    Ok(())
+}
@@ -0,0 +1,365 @@
+//! Save slots, i.e. the savegame files in [`SAVEGAME_DIR`], and the menu to manage them.
+//!
+//! There is no text rendering, so the menu is printed to the log:
+//! - Up/Down select a slot, Enter loads it, O overwrites it with the current game,
+//! - Delete deletes it (after pressing it a second time),
+//! - C converts it to the other [`SaveFormat`],
+//! - N starts typing the name of a new slot, which is saved with Enter or discarded with Escape,
+//! - Escape closes the menu.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    input::{keyboard::Key, keyboard::KeyboardInput, ButtonState},
+    prelude::*,
+};
+use serde_json::Value;
+
+use super::format::{self, SaveFormat};
+use crate::input::{CameraInput, MenuInput, MenuState, SaveMenuAction, SaveMenuInput};
+
+/// The directory with all savegames.
+pub const SAVEGAME_DIR: &str = "savegame";
+/// The slot loaded on startup.
+pub const DEFAULT_SLOT: &str = "stupid";
+
+/// How many autosaves are kept, the newest is `autosave-1`.
+const AUTOSAVE_COUNT: usize = 3;
+
+/// Returns the file of the slot `name` in the given format.
+pub fn slot_path(name: &str, format: SaveFormat) -> PathBuf {
+    PathBuf::from(SAVEGAME_DIR).join(format!("{name}.{}", format.extension()))
+}
+
+/// Returns the existing file of the slot `name` and its format, if there is one.
+pub fn slot_file(name: &str) -> Option<(PathBuf, SaveFormat)> {
+    SaveFormat::ALL
+        .into_iter()
+        .map(|format| (slot_path(name, format), format))
+        .find(|(path, _)| path.exists())
+}
+
+/// Deletes the files of the slot `name` in all formats.
+pub fn delete_slot(name: &str) -> io::Result<()> {
+    for format in SaveFormat::ALL {
+        match fs::remove_file(slot_path(name, format)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the slot `name` in the other format, returning the new one.
+pub fn convert_slot(name: &str) -> Result<SaveFormat, Box<dyn Error>> {
+    let (path, format) = slot_file(name).ok_or("Slot doesn't exist")?;
+    let converted = format::convert(&fs::read(&path)?, format.other())?;
+    write_atomically(&slot_path(name, format.other()), &converted)?;
+    fs::remove_file(path)?;
+    Ok(format.other())
+}
+
+/// Writes `data` to a temporary file first and then renames it to `path`,
+/// so `path` is never left half written, e.g. if the game crashes or the disk is full.
+pub fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    let result = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(err) = result {
+        // Don't leave the partial file lying around, the error is the interesting part
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    fs::rename(&temp_path, path)
+}
+
+/// Returns the name of the `n`th newest autosave slot, starting at 1.
+pub fn autosave_slot(n: usize) -> String {
+    format!("autosave-{n}")
+}
+
+/// Shifts all autosaves one back to make room for a new `autosave-1`, dropping the oldest.
+pub fn rotate_autosaves() -> io::Result<()> {
+    delete_slot(&autosave_slot(AUTOSAVE_COUNT))?;
+    for n in (1..AUTOSAVE_COUNT).rev() {
+        if let Some((path, format)) = slot_file(&autosave_slot(n)) {
+            fs::rename(path, slot_path(&autosave_slot(n + 1), format))?;
+        }
+    }
+    Ok(())
+}
+
+/// A savegame file, with some metadata to show in the menu.
+#[derive(Debug, Clone)]
+pub struct SlotInfo {
+    pub name: String,
+    pub format: SaveFormat,
+    /// `None` if the file couldn't be read.
+    pub version: Option<u32>,
+    pub trains: usize,
+    pub tracks: usize,
+    pub modified: Option<SystemTime>,
+}
+
+impl SlotInfo {
+    fn read(name: String, path: &Path, format: SaveFormat) -> Self {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let savegame = fs::read(path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|data| format::decode(&data));
+
+        let Ok((savegame, _)) = savegame else {
+            return Self {
+                name,
+                format,
+                version: None,
+                trains: 0,
+                tracks: 0,
+                modified,
+            };
+        };
+        let count = |value: &Value| value.as_array().map_or(0, Vec::len);
+        Self {
+            name,
+            format,
+            version: savegame["version"].as_u64().map(|v| v as u32),
+            trains: count(&savegame["trains"]),
+            // Every track has an edge in either direction
+            tracks: count(&savegame["network"]["graph"]["edges"]) / 2,
+            modified,
+        }
+    }
+}
+
+impl fmt::Display for SlotInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = format!("{} ({})", self.name, self.format.extension());
+        let Some(version) = self.version else {
+            return write!(f, "{name}: unreadable");
+        };
+        write!(
+            f,
+            "{name}: v{version}, {} trains, {} tracks",
+            self.trains, self.tracks
+        )?;
+        if let Some(age) = self.modified.and_then(|m| m.elapsed().ok()) {
+            write!(f, ", saved {}", format_age(age))?;
+        }
+        Ok(())
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..60 => format!("{minutes} min ago"),
+        60..1440 => format!("{} h ago", minutes / 60),
+        _ => format!("{} days ago", minutes / 1440),
+    }
+}
+
+/// Returns all slots in [`SAVEGAME_DIR`], sorted by name.
+pub fn list_slots() -> Vec<SlotInfo> {
+    let Ok(entries) = fs::read_dir(SAVEGAME_DIR) else {
+        return Vec::new();
+    };
+    let mut slots: Vec<SlotInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let format = SaveFormat::from_path(&path)?;
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some(SlotInfo::read(name, &path, format))
+        })
+        .collect();
+    slots.sort_by(|a, b| a.name.cmp(&b.name));
+    slots
+}
+
+/// The state of the save menu, and which slot is in use.
+#[derive(Resource)]
+pub struct SaveSlots {
+    /// The slot used by quick save and reload, i.e. the one last saved or loaded.
+    pub current: String,
+    /// The slots on disk, updated whenever the menu is opened or changed.
+    slots: Vec<SlotInfo>,
+    selected: usize,
+    /// The name of a new slot, while it is typed.
+    new_name: Option<String>,
+    /// Delete was pressed once for the selected slot.
+    confirm_delete: bool,
+    /// What the menu wants to do, which needs the whole world and is done by `save_system`.
+    pub(super) request: Option<SlotRequest>,
+}
+
+impl Default for SaveSlots {
+    fn default() -> Self {
+        Self {
+            current: DEFAULT_SLOT.to_string(),
+            slots: Vec::new(),
+            selected: 0,
+            new_name: None,
+            confirm_delete: false,
+            request: None,
+        }
+    }
+}
+
+pub(super) enum SlotRequest {
+    Save(String),
+    Load(String),
+    NewGame,
+    Autosave,
+    /// Saves and reloads the [`REPLAY_START_SLOT`](crate::replay::REPLAY_START_SLOT) and
+    /// records the commands from then on.
+    StartRecording,
+    StopRecording,
+}
+
+impl SaveSlots {
+    fn selected_slot(&self) -> Option<&SlotInfo> {
+        self.slots.get(self.selected)
+    }
+
+    /// Reads the slots from disk again and prints them.
+    pub fn refresh(&mut self) {
+        self.slots = list_slots();
+        self.selected = self
+            .slots
+            .iter()
+            .position(|slot| slot.name == self.current)
+            .unwrap_or(0);
+        self.print();
+    }
+
+    fn print(&self) {
+        if self.slots.is_empty() {
+            info!("No savegames in {SAVEGAME_DIR}/, press N to save as a new slot");
+        }
+        for (index, slot) in self.slots.iter().enumerate() {
+            let marker = if index == self.selected { ">" } else { " " };
+            info!("{marker} {slot}");
+        }
+    }
+}
+
+// ================================ SYSTEMS ===================================
+
+pub(super) fn refresh_slots_system(mut slots: ResMut<SaveSlots>) {
+    slots.refresh();
+}
+
+/// System to navigate the save menu, see the module documentation for the keys.
+pub(super) fn slot_menu_system(
+    mut slots: ResMut<SaveSlots>,
+    mut keyboard: MessageReader<KeyboardInput>,
+    input: Single<&SaveMenuInput>,
+    mut menu_input: Single<&mut MenuInput>,
+    mut camera_input: Single<&mut CameraInput>,
+    mut next_state: ResMut<NextState<MenuState>>,
+) {
+    if let Some(mut name) = slots.new_name.take() {
+        let mut finished = false;
+        for key in keyboard
+            .read()
+            .filter(|key| key.state == ButtonState::Pressed)
+        {
+            match &key.logical_key {
+                Key::Character(text) => name.extend(
+                    text.chars()
+                        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_'),
+                ),
+                Key::Backspace => {
+                    name.pop();
+                }
+                Key::Enter if !name.is_empty() => {
+                    slots.request = Some(SlotRequest::Save(name.clone()));
+                    finished = true;
+                    break;
+                }
+                Key::Escape => {
+                    info!("Discarded new slot");
+                    finished = true;
+                    break;
+                }
+                _ => continue,
+            }
+            info!("New slot name: {name}_");
+        }
+
+        if finished {
+            menu_input.enable();
+            camera_input.enable();
+        } else {
+            slots.new_name = Some(name);
+        }
+        return;
+    }
+    keyboard.clear();
+
+    if input.just_pressed(&SaveMenuAction::Close) {
+        next_state.set(MenuState::Driving);
+        return;
+    }
+    if input.just_pressed(&SaveMenuAction::SaveAs) {
+        info!("Type the name of the new slot, then press Enter");
+        slots.new_name = Some(String::new());
+        slots.confirm_delete = false;
+        // Typing must not trigger any other actions
+        menu_input.disable();
+        camera_input.disable();
+        return;
+    }
+
+    let count = slots.slots.len();
+    if count == 0 {
+        return;
+    }
+    if input.just_pressed(&SaveMenuAction::Previous) {
+        slots.selected = (slots.selected + count - 1) % count;
+        slots.confirm_delete = false;
+        slots.print();
+    }
+    if input.just_pressed(&SaveMenuAction::Next) {
+        slots.selected = (slots.selected + 1) % count;
+        slots.confirm_delete = false;
+        slots.print();
+    }
+
+    let Some(name) = slots.selected_slot().map(|slot| slot.name.clone()) else {
+        return;
+    };
+    if input.just_pressed(&SaveMenuAction::Load) {
+        slots.request = Some(SlotRequest::Load(name));
+    } else if input.just_pressed(&SaveMenuAction::Overwrite) {
+        slots.request = Some(SlotRequest::Save(name));
+    } else if input.just_pressed(&SaveMenuAction::Delete) {
+        if !slots.confirm_delete {
+            info!("Press Delete again to delete {name}");
+            slots.confirm_delete = true;
+            return;
+        }
+        slots.confirm_delete = false;
+        match delete_slot(&name) {
+            Ok(()) => info!("Deleted slot {name}"),
+            Err(err) => error!("Couldn't delete slot {name}: {err}"),
+        }
+        slots.refresh();
+    } else if input.just_pressed(&SaveMenuAction::Convert) {
+        match convert_slot(&name) {
+            Ok(format) => info!("Converted slot {name} to {format:?}"),
+            Err(err) => error!("Couldn't convert slot {name}: {err}"),
+        }
+        slots.refresh();
+    }
+}
@@ -0,0 +1,311 @@
+//! This module implements block signals.
+//!
+//! Signals are placed on [`Joint`]s of the [`RailGraph`] and split the network into blocks,
+//! where a block is a set of tracks connected without any signal in between.
+//! A signal shows
+//! - red, if the block behind it is occupied by a train,
+//! - yellow, if the block is free, but the next signal is red,
+//! - green otherwise.
+//!
+//! Trains brake in time to stop in front of red signals, see [`obey_signals`], and drive on
+//! as their driver wants once the signal clears.
+
+use std::collections::HashMap;
+use std::ops::Add;
+
+use bevy::color::palettes;
+use bevy::prelude::*;
+use petgraph::EdgeDirection;
+
+use crate::railroad::{NetworkRoot, RailGraph};
+use crate::sprites::SpriteAssets;
+use crate::tilemap::{Joint, TILE_SCALE};
+use crate::trains::*;
+
+/// How many tracks are followed at most to find the next signal.
+const SIGNAL_LOOKAHEAD: usize = 64;
+/// The distance in tracks, which trains try to keep to red signals in addition
+/// to their braking distance.
+const SIGNAL_MARGIN: f32 = 0.5;
+
+pub struct SignalPlugin;
+impl Plugin for SignalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockMap>()
+            .init_resource::<BlockOccupancy>()
+            .add_systems(
+                FixedUpdate,
+                (update_blocks, update_occupancy, obey_signals)
+                    .chain()
+                    .in_set(ControlSet::Protect),
+            );
+    }
+}
+
+/// Shows the signals and their aspects.
+pub struct SignalUiPlugin;
+impl Plugin for SignalUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (spawn_signal_markers, update_signal_tint).chain(),
+        );
+    }
+}
+
+/// The sprite of a signal at this joint.
+#[derive(Component)]
+pub struct SignalMarker(pub Joint);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalAspect {
+    Red,
+    Yellow,
+    Green,
+}
+
+/// Partition of all tracks into blocks, recomputed whenever the [`RailGraph`] changes.
+#[derive(Resource, Default)]
+pub struct BlockMap {
+    /// The block id for every edge in the graph. Both edges of a track are in the same block.
+    blocks: HashMap<(Joint, Joint), usize>,
+}
+
+/// The trains in each block, recomputed every tick from the trains [`Trail`]s.
+#[derive(Resource, Default)]
+pub struct BlockOccupancy {
+    trains: HashMap<usize, Vec<Entity>>,
+}
+
+impl BlockMap {
+    /// Computes the blocks with a union find over all edges.
+    fn from_graph(rail_graph: &RailGraph) -> Self {
+        fn find(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+        fn union(parents: &mut [usize], a: usize, b: usize) {
+            let (root_a, root_b) = (find(parents, a), find(parents, b));
+            parents[root_a] = root_b;
+        }
+
+        let indices: HashMap<(Joint, Joint), usize> = rail_graph
+            .graph
+            .all_edges()
+            .enumerate()
+            .map(|(index, (from, to, _))| ((from, to), index))
+            .collect();
+        let mut parents: Vec<usize> = (0..indices.len()).collect();
+
+        for (&(from, to), &index) in indices.iter() {
+            // Both directions of a track are in the same block.
+            if let Some(&reverse) = indices.get(&(to.opposite(), from.opposite())) {
+                union(&mut parents, index, reverse);
+            }
+            // Tracks following each other without a signal are in the same block.
+            if !rail_graph.is_block_boundary(to) {
+                for next in rail_graph
+                    .graph
+                    .neighbors_directed(to, EdgeDirection::Outgoing)
+                {
+                    union(&mut parents, index, indices[&(to, next)]);
+                }
+            }
+        }
+
+        let blocks = indices
+            .iter()
+            .map(|(&edge, &index)| (edge, find(&mut parents, index)))
+            .collect();
+        Self { blocks }
+    }
+
+    /// Returns the block of the track from `from` to `to`.
+    pub fn block_of(&self, from: Joint, to: Joint) -> Option<usize> {
+        self.blocks.get(&(from, to)).copied()
+    }
+}
+
+impl BlockOccupancy {
+    /// Returns true if any train other than `ignored` is in `block`.
+    pub fn is_occupied(&self, block: usize, ignored: Option<Entity>) -> bool {
+        self.trains
+            .get(&block)
+            .is_some_and(|trains| trains.iter().any(|&t| Some(t) != ignored))
+    }
+}
+
+/// Returns the aspect of the signal at `signal` as seen by the train `ignored`,
+/// i.e. this train doesn't count as occupying any block.
+///
+/// Only the route given by the current switch settings is considered.
+pub fn signal_aspect(
+    rail_graph: &RailGraph,
+    blocks: &BlockMap,
+    occupancy: &BlockOccupancy,
+    signal: Joint,
+    ignored: Option<Entity>,
+) -> SignalAspect {
+    let is_free = |joint: Joint| {
+        rail_graph
+            .route_from(joint)
+            .and_then(|track| blocks.block_of(track.joint, track.end_joint()))
+            .is_some_and(|block| !occupancy.is_occupied(block, ignored))
+    };
+
+    if !is_free(signal) {
+        return SignalAspect::Red;
+    }
+    let mut joint = signal;
+    for _ in 0..SIGNAL_LOOKAHEAD {
+        let Some(track) = rail_graph.route_from(joint) else {
+            break;
+        };
+        joint = track.end_joint();
+        if rail_graph.signals.contains(&joint) {
+            return if is_free(joint) {
+                SignalAspect::Green
+            } else {
+                SignalAspect::Yellow
+            };
+        }
+    }
+    SignalAspect::Green
+}
+
+// ================================ SYSTEMS ===================================
+
+fn update_blocks(rail_graph: Res<RailGraph>, mut blocks: ResMut<BlockMap>) {
+    if rail_graph.is_changed() {
+        *blocks = BlockMap::from_graph(&rail_graph);
+    }
+}
+
+fn update_occupancy(
+    trains: Query<(Entity, &Trail), With<TrainMarker>>,
+    blocks: Res<BlockMap>,
+    mut occupancy: ResMut<BlockOccupancy>,
+) {
+    occupancy.trains.clear();
+    for (train, trail) in &trains {
+        for edge in trail.trim().windows(2) {
+            let Some(block) = blocks.block_of(edge[0], edge[1]) else {
+                continue;
+            };
+            let trains_in_block = occupancy.trains.entry(block).or_default();
+            if !trains_in_block.contains(&train) {
+                trains_in_block.push(train);
+            }
+        }
+    }
+}
+
+/// System to make trains brake in time for red signals ahead, overriding their [`Controller`]
+/// with their [`Protection`] as long as the signal is red.
+fn obey_signals(
+    rail_graph: Res<RailGraph>,
+    blocks: Res<BlockMap>,
+    occupancy: Res<BlockOccupancy>,
+    mut trains: Query<
+        (Entity, &Trail, &Velocity, &Vehicles, &mut Protection),
+        (With<TrainMarker>, Without<Crashed>),
+    >,
+    vehicles: Query<&VehicleStats>,
+) {
+    for (train, trail, velocity, train_vehicles, mut protection) in trains.iter_mut() {
+        let total_stats = train_vehicles
+            .iter()
+            .filter_map(|id| vehicles.get(id).ok())
+            .fold(VehicleStats::additive_identiy(), VehicleStats::add);
+        let stopping_distance =
+            total_stats.braking_distance(velocity.velocity) / METER_PER_TRACK + SIGNAL_MARGIN;
+
+        // Walk along the joints in front of the train, first along the path then the network.
+        let front_index = trail.path_progress.ceil() as usize;
+        let Some(&first_joint) = trail.path.get(front_index) else {
+            continue;
+        };
+        let mut lead = trail.path[front_index + 1..].iter();
+        let mut joint = first_joint;
+        // The rest of the track the front is on, in real track length like the braking distance
+        let mut distance = front_index.checked_sub(1).map_or(0.0, |back| {
+            (front_index as f32 - trail.path_progress) * track_length(trail.path[back], first_joint)
+        });
+
+        while distance <= stopping_distance {
+            if rail_graph.signals.contains(&joint) {
+                let aspect = signal_aspect(&rail_graph, &blocks, &occupancy, joint, Some(train));
+                if aspect == SignalAspect::Red {
+                    protection.brake = 1.0;
+                }
+                break;
+            }
+            let next = match lead.next() {
+                Some(&next) => next,
+                None => match rail_graph.route_from(joint) {
+                    Some(track) => track.end_joint(),
+                    None => break,
+                },
+            };
+            distance += track_length(joint, next);
+            joint = next;
+        }
+    }
+}
+
+/// This system respawns all signal sprites, whenever the [`RailGraph`] changed.
+fn spawn_signal_markers(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    rail_graph: Res<RailGraph>,
+    root_query: Query<Entity, With<NetworkRoot>>,
+    markers: Query<Entity, With<SignalMarker>>,
+) {
+    if !rail_graph.is_changed() {
+        return;
+    }
+    let Ok(root_entity) = root_query.single() else {
+        // Not loaded yet
+        return;
+    };
+
+    for marker in &markers {
+        commands.entity(marker).despawn();
+    }
+    for &joint in rail_graph.signals.iter() {
+        // Trains pass the joint going against its side, the signal is to the right of them.
+        let travel_angle = joint.side.to_angle() + std::f32::consts::PI;
+        let right = Vec2::from_angle(travel_angle - std::f32::consts::FRAC_PI_2);
+
+        let mut sprite = assets.signal_sprite();
+        sprite.transform.translation +=
+            (joint.world_position() + right * TILE_SCALE * 0.15).extend(0.);
+        commands.entity(root_entity).with_children(|c| {
+            c.spawn((
+                sprite,
+                Name::new(format!("Signal {:?}", joint.tile)),
+                SignalMarker(joint),
+            ));
+        });
+    }
+}
+
+fn update_signal_tint(
+    rail_graph: Res<RailGraph>,
+    blocks: Res<BlockMap>,
+    occupancy: Res<BlockOccupancy>,
+    mut markers: Query<(&SignalMarker, &mut Sprite)>,
+) {
+    for (marker, mut sprite) in markers.iter_mut() {
+        let aspect = signal_aspect(&rail_graph, &blocks, &occupancy, marker.0, None);
+        sprite.color = match aspect {
+            SignalAspect::Red => palettes::basic::RED,
+            SignalAspect::Yellow => palettes::basic::YELLOW,
+            SignalAspect::Green => palettes::basic::LIME,
+        }
+        .into();
+    }
+}
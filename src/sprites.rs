@@ -9,7 +9,7 @@
 
 use bevy::prelude::*;
 
-use crate::tilemap::TILE_SCALE;
+use crate::tilemap::{TILE_SCALE, TILE_WIDTH};
 
 /// Texture resolution for a single tile.
 const TILE_RESOLUTION: u32 = 128;
@@ -17,7 +17,11 @@ const TILE_RESOLUTION: u32 = 128;
 const TILE_PADDING: u32 = 1;
 
 const Z_LAYER_TERRAIN: f32 = 0.1;
+const Z_LAYER_PLATFORMS: f32 = 0.15;
 const Z_LAYER_RAILS: f32 = 0.2;
+const Z_LAYER_SWITCHES: f32 = 0.25;
+const Z_LAYER_SIGNALS: f32 = 0.27;
+const Z_LAYER_PREVIEW: f32 = 0.28;
 const Z_LAYER_TRAINS: f32 = 0.3;
 
 pub struct AssetPlugin;
@@ -27,7 +31,7 @@ impl Plugin for AssetPlugin {
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct SpriteAssets {
     terrain: (Handle<TextureAtlasLayout>, Handle<Image>),
     rails: (Handle<TextureAtlasLayout>, Handle<Image>),
@@ -87,10 +91,45 @@ impl SpriteAssets {
         Self::sprite_bundle(&self.terrain, sprite as usize, Z_LAYER_TERRAIN)
     }
 
+    /// A plain rectangle for a section of a station platform, lengthwise along the x axis.
+    pub fn platform_sprite(&self) -> BaseSpriteBundle {
+        BaseSpriteBundle {
+            sprite: Sprite::from_color(
+                Color::srgb(0.75, 0.7, 0.6),
+                Vec2::new(0.9 * TILE_WIDTH, 0.12 * TILE_SCALE),
+            ),
+            transform: Transform::from_translation(Vec3::Z * Z_LAYER_PLATFORMS),
+        }
+    }
+
     pub fn rail_sprite(&self, sprite: RailSprite) -> BaseSpriteBundle {
         Self::sprite_bundle(&self.rails, sprite as usize, Z_LAYER_RAILS)
     }
 
+    /// A tinted rail sprite above the normal rails, used to highlight the selected
+    /// track of a switch.
+    pub fn switch_sprite(&self, sprite: RailSprite) -> BaseSpriteBundle {
+        let mut bundle = Self::sprite_bundle(&self.rails, sprite as usize, Z_LAYER_SWITCHES);
+        bundle.sprite.color = Srgba::new(1.0, 0.8, 0.1, 0.8).into();
+        bundle
+    }
+
+    /// A translucent rail sprite above the rails and signals, used to preview tracks
+    /// before they are built.
+    pub fn preview_sprite(&self, sprite: RailSprite) -> BaseSpriteBundle {
+        let mut bundle = Self::sprite_bundle(&self.rails, sprite as usize, Z_LAYER_PREVIEW);
+        bundle.sprite.color = Srgba::new(1.0, 1.0, 1.0, 0.5).into();
+        bundle
+    }
+
+    /// A plain square for signals, which should be tinted according to the aspect.
+    pub fn signal_sprite(&self) -> BaseSpriteBundle {
+        BaseSpriteBundle {
+            sprite: Sprite::from_color(Color::WHITE, Vec2::splat(TILE_SCALE * 0.12)),
+            transform: Transform::from_translation(Vec3::Z * Z_LAYER_SIGNALS),
+        }
+    }
+
     pub fn vehicle_sprite(&self, sprite: VehicleSprite) -> BaseSpriteBundle {
         Self::sprite_bundle(&self.vehicles, sprite as usize, Z_LAYER_TRAINS)
     }
@@ -0,0 +1,192 @@
+//! This module defines stations, which are places along the tracks trains can stop at.
+//!
+//! A station is an entity with a [`Station`] component, which is a child of the [`NetworkRoot`]
+//! and has the platform sprites as children.
+//! In building mode, clicking a track adds it to an adjacent station or creates a new one,
+//! while clicking a track of a station removes it again.
+
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+use petgraph::EdgeDirection;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::SimCommand;
+use crate::input::{BuildingState, MenuState};
+use crate::interact::TileClickEvent;
+use crate::railroad::{NetworkRoot, RailGraph};
+use crate::sprites::SpriteAssets;
+use crate::tilemap::{Joint, TILE_SCALE};
+use crate::trains::{Trail, Velocity};
+
+pub struct StationPlugin;
+impl Plugin for StationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            station_builder
+                .run_if(in_state(MenuState::Building))
+                .run_if(in_state(BuildingState::PlaceStation)),
+        )
+        .add_systems(PostUpdate, update_platforms);
+    }
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct Station {
+    pub name: String,
+    /// The joints along the platform.
+    ///
+    /// The orientation of a joint only determines on which side the platform is drawn,
+    /// for everything else both orientations are considered to be part of the station.
+    pub joints: BTreeSet<Joint>,
+}
+
+impl Station {
+    /// Returns true if `joint` (in either orientation) is part of this station.
+    pub fn contains(&self, joint: Joint) -> bool {
+        self.joints.contains(&joint) || self.joints.contains(&joint.opposite())
+    }
+
+    /// Returns true if the train is standing still with all of its vehicles along the platform.
+    pub fn has_stopped(&self, trail: &Trail, velocity: &Velocity) -> bool {
+        velocity.velocity == 0.0 && trail.trim().iter().all(|&joint| self.contains(joint))
+    }
+
+    /// Returns all the trains which have stopped at this station, see [`Station::has_stopped`].
+    pub fn stopped_trains<'a>(
+        &self,
+        trains: impl IntoIterator<Item = (Entity, &'a Trail, &'a Velocity)>,
+    ) -> Vec<Entity> {
+        trains
+            .into_iter()
+            .filter(|(_, trail, velocity)| self.has_stopped(trail, velocity))
+            .map(|(train, _, _)| train)
+            .collect()
+    }
+}
+
+/// Generates the bundle for a station entity, the platforms are added automatically.
+pub fn station_bundle(station: Station) -> impl Bundle {
+    (
+        Name::new(station.name.clone()),
+        station,
+        Transform::default(),
+        Visibility::default(),
+    )
+}
+
+/// This system adds or removes tracks from stations when clicked.
+fn station_builder(
+    mut click_event: MessageReader<TileClickEvent>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    rail_graph: Res<RailGraph>,
+) {
+    for evt in click_event.read() {
+        let Some(side) = evt.side else {
+            continue;
+        };
+        let joint = Joint {
+            tile: evt.coord,
+            side,
+        };
+        if !rail_graph.graph.contains_node(joint) {
+            continue;
+        }
+        sim_commands.write(SimCommand::ToggleStation(joint));
+    }
+}
+
+/// Adds the track at `joint` to an adjacent station or creates a new one,
+/// or removes it if it is part of a station already.
+pub(crate) fn toggle_station(
+    In(joint): In<Joint>,
+    mut commands: Commands,
+    rail_graph: Res<RailGraph>,
+    root_query: Query<Entity, With<NetworkRoot>>,
+    mut stations: Query<(Entity, &mut Station)>,
+) {
+    if !rail_graph.graph.contains_node(joint) {
+        warn!(
+            "Cannot build a station @{:?}, there is no track",
+            joint.tile
+        );
+        return;
+    }
+
+    // Clicking a station again removes the joint
+    if let Some((station_id, mut station)) = stations.iter_mut().find(|(_, s)| s.contains(joint)) {
+        station.joints.remove(&joint);
+        station.joints.remove(&joint.opposite());
+        if station.joints.is_empty() {
+            info!("Removed station {}", station.name);
+            commands.entity(station_id).despawn();
+        }
+        return;
+    }
+
+    // Extend a station which is connected by a track
+    let neighbors = [joint, joint.opposite()].into_iter().flat_map(|j| {
+        rail_graph
+            .graph
+            .neighbors_directed(j, EdgeDirection::Outgoing)
+            .chain(
+                rail_graph
+                    .graph
+                    .neighbors_directed(j, EdgeDirection::Incoming),
+            )
+    });
+    let adjacent_station = neighbors
+        .filter_map(|n| stations.iter().find(|(_, s)| s.contains(n)))
+        .map(|(id, _)| id)
+        .next();
+    if let Some(station_id) = adjacent_station {
+        let (_, mut station) = stations
+            .get_mut(station_id)
+            .expect("id was found by the same query");
+        station.joints.insert(joint);
+        return;
+    }
+
+    let mut number = stations.iter().count() + 1;
+    while stations
+        .iter()
+        .any(|(_, s)| s.name == format!("Station {number}"))
+    {
+        number += 1;
+    }
+    let station = Station {
+        name: format!("Station {number}"),
+        joints: BTreeSet::from([joint]),
+    };
+    info!("Built station {} @{:?}", station.name, joint.tile);
+    let station = commands.spawn(station_bundle(station)).id();
+    // In a headless simulation, there may be no root
+    if let Ok(root_entity) = root_query.single() {
+        commands.entity(root_entity).add_child(station);
+    }
+}
+
+/// This system respawns the platform sprites of changed stations.
+fn update_platforms(
+    mut commands: Commands,
+    assets: Res<SpriteAssets>,
+    stations: Query<(Entity, &Station), Changed<Station>>,
+) {
+    for (station_id, station) in &stations {
+        commands.entity(station_id).despawn_children();
+        for &joint in station.joints.iter() {
+            // Trains pass the joint going against its side, the platform is to the right of them.
+            let travel_angle = joint.side.to_angle() + std::f32::consts::PI;
+            let right = Vec2::from_angle(travel_angle - std::f32::consts::FRAC_PI_2);
+
+            let mut sprite = assets.platform_sprite();
+            sprite.transform.rotate_z(travel_angle);
+            sprite.transform.translation +=
+                (joint.world_position() + right * TILE_SCALE * 0.3).extend(0.);
+            commands.entity(station_id).with_children(|c| {
+                c.spawn((sprite, Name::new(format!("Platform {:?}", joint.tile))));
+            });
+        }
+    }
+}
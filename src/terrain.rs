@@ -1,17 +1,48 @@
+//! The terrain of the map, generated from a seed.
+//!
+//! The map is a hexagon of tiles with a radius of [`MAP_RADIUS`], every tile has a
+//! [`TerrainType`] given by two noise fields: the elevation decides between water, land,
+//! hills and mountains, the moisture grows forests on the land in between.
+//! Only the seed is saved, the [`TerrainMap`] is generated again on load.
+
+use std::collections::HashMap;
+
 use bevy::prelude::*;
-use rand::seq::SliceRandom;
 
 use crate::sprites::{SpriteAssets, TerrainSprite};
-use crate::tilemap::Tile;
+use crate::tilemap::{Tile, TILE_SCALE};
+
+/// The number of tiles from the center to the edge of the map.
+pub const MAP_RADIUS: i32 = 20;
 
+/// The size of the features of the terrain, in tiles.
+const NOISE_SCALE: f32 = 7.0;
+const NOISE_OCTAVES: u32 = 3;
+/// Elevations below are water.
+const WATER_LEVEL: f32 = 0.32;
+/// Elevations above are hills.
+const HILL_LEVEL: f32 = 0.6;
+/// Elevations above are mountains.
+const MOUNTAIN_LEVEL: f32 = 0.72;
+/// Land with a moisture above is a forest.
+const FOREST_MOISTURE: f32 = 0.6;
+
+/// Provides the [`TerrainMap`], part of the [`SimulationPlugins`](crate::SimulationPlugins).
 pub struct TerrainPlugin;
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
-        // Todo: this need to be revamped with better ordering
-        // but simply setting .before /.after doesn't work?
-        // Maybe because the commands are only run after the stage
-        app.add_systems(PreStartup, spawn_terrain_root)
-            .add_systems(Startup, spawn_tiles);
+        app.init_resource::<TerrainMap>();
+    }
+}
+
+/// Draws the [`TerrainMap`], whenever it changes.
+pub struct TerrainUiPlugin;
+impl Plugin for TerrainUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, spawn_terrain_root).add_systems(
+            Update,
+            spawn_tiles.run_if(resource_exists_and_changed::<TerrainMap>),
+        );
     }
 }
 
@@ -21,10 +52,139 @@ struct TerrainRoot;
 #[derive(Component)]
 struct TileMarker;
 
-enum TerrainType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerrainType {
     Land,
+    Water,
+    Forest,
+    Hills,
+    Mountains,
+}
+
+impl TerrainType {
+    /// The tint of the land sprites, there are no sprites for the other types yet.
+    fn color(self) -> Color {
+        match self {
+            TerrainType::Land => Color::WHITE,
+            TerrainType::Water => Color::srgb(0.35, 0.55, 0.95),
+            TerrainType::Forest => Color::srgb(0.45, 0.75, 0.4),
+            TerrainType::Hills => Color::srgb(0.85, 0.75, 0.55),
+            TerrainType::Mountains => Color::srgb(0.6, 0.6, 0.6),
+        }
+    }
+}
+
+/// The terrain of every tile on the map.
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainMap {
+    /// `None` for maps of only [`TerrainType::Land`], e.g. the ones of old savegames.
+    seed: Option<u64>,
+    tiles: HashMap<Tile, TerrainType>,
+}
+
+/// A map of only land.
+impl Default for TerrainMap {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl TerrainMap {
+    /// Generates the map for `seed`, which is always the same for the same seed.
+    pub fn new(seed: Option<u64>) -> Self {
+        let tiles = map_tiles()
+            .map(|tile| {
+                let terrain = seed.map_or(TerrainType::Land, |seed| generate_tile(seed, tile));
+                (tile, terrain)
+            })
+            .collect();
+        Self { seed, tiles }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Returns the terrain of `tile`, or `None` if it is not on the map.
+    pub fn get(&self, tile: Tile) -> Option<TerrainType> {
+        self.tiles.get(&tile).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Tile, TerrainType)> + '_ {
+        self.tiles.iter().map(|(&tile, &terrain)| (tile, terrain))
+    }
+}
+
+/// All tiles of the map, a hexagon around the origin.
+fn map_tiles() -> impl Iterator<Item = Tile> {
+    (-MAP_RADIUS..=MAP_RADIUS).flat_map(|y| {
+        let xs = if y >= 0 {
+            -MAP_RADIUS..=(MAP_RADIUS - y)
+        } else {
+            (-MAP_RADIUS - y)..=MAP_RADIUS
+        };
+        xs.map(move |x| Tile(x, y))
+    })
+}
+
+fn generate_tile(seed: u64, tile: Tile) -> TerrainType {
+    // In tiles, such that the features aren't skewed by the axial coordinates
+    let position = tile.world_pos() / TILE_SCALE / NOISE_SCALE;
+    let elevation = fractal_noise(seed, position);
+    // A different seed for an independent field
+    let moisture = fractal_noise(seed ^ 0x6d6f_6973_7475_7265, position);
+    if elevation < WATER_LEVEL {
+        TerrainType::Water
+    } else if elevation > MOUNTAIN_LEVEL {
+        TerrainType::Mountains
+    } else if elevation > HILL_LEVEL {
+        TerrainType::Hills
+    } else if moisture > FOREST_MOISTURE {
+        TerrainType::Forest
+    } else {
+        TerrainType::Land
+    }
 }
 
+/// Sums [`NOISE_OCTAVES`] layers of [`value_noise`], each with double the frequency and half
+/// the amplitude of the previous one. The result is in [0, 1].
+fn fractal_noise(seed: u64, position: Vec2) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    for octave in 0..NOISE_OCTAVES {
+        let frequency = (1 << octave) as f32;
+        sum += amplitude * value_noise(seed.wrapping_add(octave as u64), position * frequency);
+        total_amplitude += amplitude;
+        amplitude /= 2.0;
+    }
+    sum / total_amplitude
+}
+
+/// Smooth noise in [0, 1], interpolating random values at the integer points.
+fn value_noise(seed: u64, position: Vec2) -> f32 {
+    let cell = position.floor();
+    let t = position - cell;
+    // Smoothstep, so there are no visible edges between the cells
+    let t = t * t * (3.0 - 2.0 * t);
+    let (x, y) = (cell.x as i32, cell.y as i32);
+    let corner = |dx: i32, dy: i32| hash(seed, x + dx, y + dy) as f32 / u32::MAX as f32;
+    let bottom = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * t.x;
+    let top = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * t.x;
+    bottom + (top - bottom) * t.y
+}
+
+/// A pseudo random number for the point `(x, y)`, which is the same on every run.
+fn hash(seed: u64, x: i32, y: i32) -> u32 {
+    // SplitMix64 of the combined inputs
+    let mut hash = seed ^ (((x as u32 as u64) << 32) | y as u32 as u64);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (hash ^ (hash >> 31)) as u32
+}
+
+// ================================ SYSTEMS ===================================
+
 /// This system spawns the root node for all the terrain sprites, useful mostly for inspecting.
 fn spawn_terrain_root(mut commands: Commands) {
     commands
@@ -33,47 +193,42 @@ fn spawn_terrain_root(mut commands: Commands) {
         .insert(Name::new("Terrain"));
 }
 
-/// system to spawn some tiles in a hexagon
+/// System to replace the tile sprites with the ones of the current [`TerrainMap`].
 fn spawn_tiles(
     mut commands: Commands,
     assets: Res<SpriteAssets>,
+    terrain_map: Res<TerrainMap>,
     root_query: Query<Entity, With<TerrainRoot>>,
 ) {
     let root_entity = root_query.single().expect("exactly one TerrainRoot entity");
-    const RADIUS: i32 = 20;
-    for y in -RADIUS..=RADIUS {
-        for x in if y >= 0 {
-            -RADIUS..=(RADIUS - y)
-        } else {
-            (-RADIUS - y)..=RADIUS
-        } {
-            commands.entity(root_entity).with_children(|c| {
-                c.spawn(terrain_tile_bundle(&assets, Tile(x, y), TerrainType::Land));
-            });
+    commands.entity(root_entity).despawn_related::<Children>();
+    commands.entity(root_entity).with_children(|c| {
+        for (tile, terrain_type) in terrain_map.iter() {
+            c.spawn(terrain_tile_bundle(&assets, tile, terrain_type));
         }
-    }
+    });
 }
 
-/// Generates a (not deterministic) bundle for a tile entity for a given type and position
+/// Generates a bundle for a tile entity for a given type and position.
+///
+/// The sprite variant is picked by [`hash`], so the terrain looks the same every time.
 fn terrain_tile_bundle(
     assets: &SpriteAssets,
     position: Tile,
     terrain_type: TerrainType,
 ) -> impl Bundle {
-    let &sprite_id = match terrain_type {
-        TerrainType::Land => [
-            TerrainSprite::Land1,
-            TerrainSprite::Land1,
-            TerrainSprite::Land2,
-            TerrainSprite::Land3,
-            TerrainSprite::Land3,
-            TerrainSprite::Land3,
-        ]
-        .choose(&mut rand::thread_rng())
-        .expect("constant array is non-empty"),
-    };
+    let variants = [
+        TerrainSprite::Land1,
+        TerrainSprite::Land1,
+        TerrainSprite::Land2,
+        TerrainSprite::Land3,
+        TerrainSprite::Land3,
+        TerrainSprite::Land3,
+    ];
+    let sprite_id = variants[hash(0, position.0, position.1) as usize % variants.len()];
 
     let mut sprite = assets.terrain_sprite(sprite_id);
+    sprite.sprite.color = terrain_type.color();
     // Place in the world
     sprite.transform.translation += position.world_pos().extend(0.);
 
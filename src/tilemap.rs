@@ -4,11 +4,16 @@
 //! A "Joint" is a sixth of a hexagon, or equivalently an edge with a (perpendicular) orientation.
 //! A "Track" is are two joints, which either form a single straight, single left-curving or
 //! single right-curving section.
+//!
+//! Besides the axial coordinates of [`Tile`], there are cube coordinates `(x, y, z)` with
+//! `x + y + z = 0`, in which the symmetries of the grid are simpler, see [`Tile::to_cube`].
 
 use bevy::prelude::*;
 use core::fmt;
 use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
+use std::iter;
+use std::ops::{Add, Sub};
 
 /// The height (point to point) of the hexagons in world units.
 pub const TILE_SCALE: f32 = 0.5;
@@ -88,6 +93,105 @@ impl Tile {
         }
     }
 
+    /// Returns the cube coordinates of the tile, `(x, y, -x - y)`.
+    pub fn to_cube(&self) -> IVec3 {
+        IVec3::new(self.0, self.1, -self.0 - self.1)
+    }
+
+    /// Returns the tile at the cube coordinates `cube`, whose components have to sum to zero.
+    pub fn from_cube(cube: IVec3) -> Tile {
+        debug_assert_eq!(
+            cube.element_sum(),
+            0,
+            "Cube coordinates have to sum to zero"
+        );
+        Tile(cube.x, cube.y)
+    }
+
+    /// Returns the tile containing the fractional cube coordinates `cube`.
+    pub fn from_cube_rounded(cube: Vec3) -> Tile {
+        let rounded = cube.round();
+        let diff = (rounded - cube).abs();
+        // Rounding all three might break the sum, so the one rounded the most is derived
+        let (x, y) = if diff.x > diff.y && diff.x > diff.z {
+            (-rounded.y - rounded.z, rounded.y)
+        } else if diff.y > diff.z {
+            (rounded.x, -rounded.x - rounded.z)
+        } else {
+            (rounded.x, rounded.y)
+        };
+        Tile(x as i32, y as i32)
+    }
+
+    /// Returns the number of steps between this tile and `other` on the hex grid.
+    pub fn distance(&self, other: Tile) -> u32 {
+        let (dx, dy) = (self.0 - other.0, self.1 - other.1);
+        (dx.unsigned_abs() + dy.unsigned_abs() + (dx + dy).unsigned_abs()) / 2
+    }
+
+    /// Returns the tiles at exactly `radius` steps from this one, counterclockwise starting
+    /// at the east. A radius of 0 returns only this tile.
+    pub fn ring(self, radius: u32) -> impl Iterator<Item = Tile> {
+        let start = (0..radius).fold(self, |tile, _| tile.neighbor_to(Direction::EAST));
+        // From the east corner along the six sides, starting north-west
+        let steps = (2..8).flat_map(move |turns| {
+            iter::repeat_n(Direction::from_sixth_turns(turns), radius as usize)
+        });
+        let walk = steps.scan(start, |tile, dir| {
+            let current = *tile;
+            *tile = tile.neighbor_to(dir);
+            Some(current)
+        });
+        iter::once(self).filter(move |_| radius == 0).chain(walk)
+    }
+
+    /// Returns the tiles up to `radius` steps from this one, ring by ring from the inside.
+    pub fn spiral(self, radius: u32) -> impl Iterator<Item = Tile> {
+        (0..=radius).flat_map(move |ring| self.ring(ring))
+    }
+
+    /// Returns the tiles on the straight line from this tile to `to`, both included.
+    /// Consecutive tiles are neighbors.
+    pub fn line_to(self, to: Tile) -> impl Iterator<Item = Tile> {
+        let steps = self.distance(to);
+        // Nudged, so points on the edge between two tiles are always rounded the same way
+        let nudge = Vec3::new(1e-4, 2e-4, -3e-4);
+        let (from, to) = (
+            self.to_cube().as_vec3() + nudge,
+            to.to_cube().as_vec3() + nudge,
+        );
+        (0..=steps).map(move |step| {
+            let t = if steps == 0 {
+                0.
+            } else {
+                step as f32 / steps as f32
+            };
+            Tile::from_cube_rounded(from.lerp(to, t))
+        })
+    }
+
+    /// Rotates the tile counterclockwise around the origin by `sixth_turns`,
+    /// negative turns rotate clockwise.
+    pub fn rotated(&self, sixth_turns: i32) -> Tile {
+        (0..sixth_turns.rem_euclid(6)).fold(*self, |Tile(x, y), _| Tile(-y, x + y))
+    }
+
+    /// Rotates the tile counterclockwise around `center` by `sixth_turns`.
+    pub fn rotated_around(&self, center: Tile, sixth_turns: i32) -> Tile {
+        center + (*self - center).rotated(sixth_turns)
+    }
+
+    /// Mirrors the tile along the east-west axis through the origin.
+    pub fn mirrored(&self) -> Tile {
+        Tile(self.0 + self.1, -self.1)
+    }
+
+    /// Mirrors the tile along the axis through the origin in the direction `axis`.
+    pub fn reflected(&self, axis: Direction) -> Tile {
+        let turns = i32::from(axis.0);
+        self.rotated(-turns).mirrored().rotated(turns)
+    }
+
     fn nearer_tile(tile1: Tile, tile2: Tile, world_pos: Vec2) -> Tile {
         if tile1.world_pos().distance_squared(world_pos)
             < tile2.world_pos().distance_squared(world_pos)
@@ -107,9 +211,40 @@ impl Direction {
     pub const SOUTH_WEST: Direction = Direction(4);
     pub const SOUTH_EAST: Direction = Direction(5);
 
-    pub fn from_sixth_turns(turns: i8) -> Direction {
-        // Todo: make this generic over more number types
-        Direction((turns % 6) as u8)
+    /// All six directions, counterclockwise starting at the east.
+    pub const ALL: [Direction; 6] = [
+        Direction::EAST,
+        Direction::NORTH_EAST,
+        Direction::NORTH_WEST,
+        Direction::WEST,
+        Direction::SOUTH_WEST,
+        Direction::SOUTH_EAST,
+    ];
+
+    /// Returns the direction `turns` counterclockwise sixth turns from the east,
+    /// negative turns count clockwise.
+    pub fn from_sixth_turns(turns: i32) -> Direction {
+        Direction(turns.rem_euclid(6) as u8)
+    }
+
+    /// Returns the counterclockwise sixth turns from the east, in 0..6.
+    pub fn sixth_turns(&self) -> u8 {
+        self.0
+    }
+
+    /// Rotates the direction counterclockwise by `sixth_turns`.
+    pub fn rotated(&self, sixth_turns: i32) -> Direction {
+        Direction::from_sixth_turns(i32::from(self.0) + sixth_turns.rem_euclid(6))
+    }
+
+    /// Mirrors the direction along the east-west axis.
+    pub fn mirrored(&self) -> Direction {
+        Direction((6 - self.0) % 6)
+    }
+
+    /// Mirrors the direction along `axis`.
+    pub fn reflected(&self, axis: Direction) -> Direction {
+        Direction::from_sixth_turns(2 * i32::from(axis.0) - i32::from(self.0))
     }
 
     /// Returns the angle in radians (counterclockwise, starting from 0 towards +X, to 2*Pi)
@@ -117,16 +252,18 @@ impl Direction {
         self.0 as f32 * PI / 3.
     }
 
-    fn opposite(&self) -> Direction {
-        Direction::from_sixth_turns(self.0 as i8 + 3)
+    pub fn opposite(&self) -> Direction {
+        self.rotated(3)
     }
 
-    fn curve_right(&self) -> Direction {
-        Direction::from_sixth_turns(self.0 as i8 + 2)
+    /// Returns the direction a track heading the opposite way leaves to, curving right.
+    pub fn curve_right(&self) -> Direction {
+        self.rotated(2)
     }
 
-    fn curve_left(&self) -> Direction {
-        Direction::from_sixth_turns(self.0 as i8 + 4)
+    /// Returns the direction a track heading the opposite way leaves to, curving left.
+    pub fn curve_left(&self) -> Direction {
+        self.rotated(4)
     }
 }
 
@@ -158,6 +295,22 @@ impl Joint {
         }
     }
 
+    /// Rotates the joint counterclockwise around the origin by `sixth_turns`.
+    pub fn rotated(&self, sixth_turns: i32) -> Self {
+        Self {
+            tile: self.tile.rotated(sixth_turns),
+            side: self.side.rotated(sixth_turns),
+        }
+    }
+
+    /// Mirrors the joint along the east-west axis through the origin.
+    pub fn mirrored(&self) -> Self {
+        Self {
+            tile: self.tile.mirrored(),
+            side: self.side.mirrored(),
+        }
+    }
+
     /// Returns the world position of the center of the grid edge that this face represents
     pub fn world_position(self) -> Vec2 {
         let origin = self.tile.world_pos();
@@ -179,11 +332,27 @@ impl Joint {
         let angle = -diff.angle_to(Vec2::X);
         Ok(Self {
             tile: tc,
-            side: Direction::from_sixth_turns(((angle + 2. * PI) / (PI / 3.)).round() as i8),
+            side: Direction::from_sixth_turns(((angle + 2. * PI) / (PI / 3.)).round() as i32),
         })
     }
 }
 
+impl Add for Tile {
+    type Output = Tile;
+
+    fn add(self, other: Tile) -> Tile {
+        Tile(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for Tile {
+    type Output = Tile;
+
+    fn sub(self, other: Tile) -> Tile {
+        Tile(self.0 - other.0, self.1 - other.1)
+    }
+}
+
 impl fmt::Debug for Tile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({}, {})", self.0, self.1)
@@ -197,3 +366,223 @@ impl fmt::Debug for Tile {
         // )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Tiles in every sector around the origin, and the origin itself.
+    fn example_tiles() -> Vec<Tile> {
+        Tile(0, 0).spiral(4).collect()
+    }
+
+    #[test]
+    fn sixth_turns_wrap_around() {
+        assert_eq!(Direction::from_sixth_turns(6), Direction::EAST);
+        assert_eq!(Direction::from_sixth_turns(7), Direction::NORTH_EAST);
+        assert_eq!(Direction::from_sixth_turns(-1), Direction::SOUTH_EAST);
+        assert_eq!(Direction::from_sixth_turns(-6), Direction::EAST);
+        assert_eq!(Direction::from_sixth_turns(i32::MIN).sixth_turns(), 4);
+        assert_eq!(Direction::WEST.rotated(-4), Direction::SOUTH_EAST);
+        assert_eq!(Direction::SOUTH_EAST.rotated(i32::MAX), Direction::EAST);
+    }
+
+    #[test]
+    fn direction_arithmetic() {
+        for dir in Direction::ALL {
+            assert_eq!(dir.opposite().opposite(), dir);
+            assert_eq!(dir.rotated(1).rotated(-1), dir);
+            assert_eq!(dir.curve_left(), dir.opposite().rotated(1));
+            assert_eq!(dir.curve_right(), dir.opposite().rotated(-1));
+            assert_eq!(dir.mirrored(), dir.reflected(Direction::EAST));
+            assert_eq!(
+                dir.reflected(Direction::WEST),
+                dir.reflected(Direction::EAST)
+            );
+            assert_eq!(dir.reflected(dir), dir);
+            assert_eq!(
+                dir.reflected(Direction::NORTH_EAST),
+                dir.reflected(Direction::SOUTH_WEST)
+            );
+        }
+        assert_eq!(Direction::NORTH_EAST.mirrored(), Direction::SOUTH_EAST);
+        assert_eq!(
+            Direction::EAST.reflected(Direction::NORTH_EAST),
+            Direction::NORTH_WEST
+        );
+    }
+
+    #[test]
+    fn neighbors_match_directions() {
+        for dir in Direction::ALL {
+            let neighbor = Tile(0, 0).neighbor_to(dir);
+            assert_eq!(Tile(0, 0).distance(neighbor), 1);
+            assert_eq!(neighbor.neighbor_to(dir.opposite()), Tile(0, 0));
+            let angle = neighbor.world_pos().to_angle();
+            assert!((Vec2::from_angle(angle) - Vec2::from_angle(dir.to_angle())).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn cube_coordinates() {
+        for tile in example_tiles() {
+            let cube = tile.to_cube();
+            assert_eq!(cube.element_sum(), 0);
+            assert_eq!(Tile::from_cube(cube), tile);
+            assert_eq!(
+                Tile::from_cube_rounded(cube.as_vec3() + 0.3 * Vec3::X),
+                tile
+            );
+            assert_eq!(
+                (cube - Tile(1, -2).to_cube()).abs().max_element() as u32,
+                tile.distance(Tile(1, -2))
+            );
+        }
+        assert_eq!(
+            Tile::from_cube_rounded(Vec3::new(0.6, 0.5, -1.1)),
+            Tile(1, 0)
+        );
+    }
+
+    #[test]
+    fn add_and_sub() {
+        assert_eq!(Tile(1, 2) + Tile(-3, 5), Tile(-2, 7));
+        assert_eq!(Tile(1, 2) - Tile(-3, 5), Tile(4, -3));
+        for tile in example_tiles() {
+            assert_eq!(tile + Tile(3, -7) - Tile(3, -7), tile);
+            assert_eq!(
+                (tile - Tile(3, -7)).distance(Tile(0, 0)),
+                tile.distance(Tile(3, -7))
+            );
+        }
+    }
+
+    #[test]
+    fn rings() {
+        let center = Tile(2, -1);
+        assert_eq!(center.ring(0).collect::<Vec<_>>(), vec![center]);
+        for radius in 1..5 {
+            let ring: Vec<Tile> = center.ring(radius).collect();
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert_eq!(ring[0], Tile(2 + radius as i32, -1));
+            assert!(ring.iter().all(|tile| tile.distance(center) == radius));
+            assert_eq!(ring.iter().collect::<HashSet<_>>().len(), ring.len());
+            // Closed loop of neighbors
+            for (tile, next) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+                assert_eq!(tile.distance(*next), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn spirals() {
+        let center = Tile(-3, 4);
+        let spiral: Vec<Tile> = center.spiral(3).collect();
+        assert_eq!(spiral.len(), 1 + 3 * 3 * 4);
+        assert_eq!(spiral[0], center);
+        assert_eq!(spiral.iter().collect::<HashSet<_>>().len(), spiral.len());
+        assert!(spiral
+            .windows(2)
+            .all(|w| w[0].distance(center) <= w[1].distance(center)));
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            Tile(1, 1).line_to(Tile(1, 1)).collect::<Vec<_>>(),
+            vec![Tile(1, 1)]
+        );
+        assert_eq!(
+            Tile(0, 0).line_to(Tile(3, 0)).collect::<Vec<_>>(),
+            vec![Tile(0, 0), Tile(1, 0), Tile(2, 0), Tile(3, 0)]
+        );
+        for from in example_tiles() {
+            for to in [Tile(0, 0), Tile(5, -2), Tile(-4, -1)] {
+                let line: Vec<Tile> = from.line_to(to).collect();
+                assert_eq!(line.len() as u32, from.distance(to) + 1);
+                assert_eq!((line[0], line[line.len() - 1]), (from, to));
+                assert!(line.windows(2).all(|w| w[0].distance(w[1]) == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(Tile(1, 0).rotated(1), Tile(0, 1));
+        assert_eq!(Tile(1, 0).rotated(-1), Tile(1, -1));
+        let center = Tile(2, -3);
+        for tile in example_tiles() {
+            assert_eq!(tile.rotated(6), tile);
+            assert_eq!(tile.rotated(2).rotated(-2), tile);
+            assert_eq!(tile.rotated(-1), tile.rotated(5));
+            assert_eq!(tile.rotated(3), Tile(0, 0) - tile);
+            let rotated = tile.rotated_around(center, 2);
+            assert_eq!(rotated.distance(center), tile.distance(center));
+            assert_eq!(center.rotated_around(center, 1), center);
+            for dir in Direction::ALL {
+                assert_eq!(
+                    tile.neighbor_to(dir).rotated(1),
+                    tile.rotated(1).neighbor_to(dir.rotated(1))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reflections() {
+        assert_eq!(Tile(0, 1).mirrored(), Tile(1, -1));
+        for tile in example_tiles() {
+            assert_eq!(tile.mirrored(), tile.reflected(Direction::EAST));
+            for axis in Direction::ALL {
+                let reflected = tile.reflected(axis);
+                assert_eq!(reflected.reflected(axis), tile);
+                assert_eq!(reflected.distance(Tile(0, 0)), tile.distance(Tile(0, 0)));
+                for dir in Direction::ALL {
+                    assert_eq!(
+                        tile.neighbor_to(dir).reflected(axis),
+                        reflected.neighbor_to(dir.reflected(axis))
+                    );
+                }
+            }
+            // Tiles on the axis stay in place
+            assert_eq!(Tile(tile.0, 0).mirrored(), Tile(tile.0, 0));
+        }
+    }
+
+    #[test]
+    fn joints_keep_their_shape() {
+        for tile in example_tiles() {
+            for side in Direction::ALL {
+                let joint = Joint { tile, side };
+                for turns in -6..6 {
+                    let rotated = joint.rotated(turns);
+                    assert_eq!(
+                        joint.next_straight().rotated(turns),
+                        rotated.next_straight()
+                    );
+                    assert_eq!(joint.next_left().rotated(turns), rotated.next_left());
+                    assert_eq!(joint.next_right().rotated(turns), rotated.next_right());
+                }
+                let mirrored = joint.mirrored();
+                assert_eq!(joint.opposite().mirrored(), mirrored.opposite());
+                assert_eq!(joint.next_left().mirrored(), mirrored.next_right());
+                assert_eq!(joint.next_right().mirrored(), mirrored.next_left());
+            }
+        }
+    }
+
+    #[test]
+    fn world_positions_round_trip() {
+        for tile in example_tiles() {
+            assert_eq!(Tile::from_world_pos(tile.world_pos()), tile);
+            for side in Direction::ALL {
+                let joint = Joint { tile, side };
+                // Slightly inside the tile, since the edge itself is ambiguous
+                let pos = tile.world_pos() + (joint.world_position() - tile.world_pos()) * 0.9;
+                assert_eq!(Joint::from_world_pos(pos), Ok(joint));
+            }
+        }
+    }
+}
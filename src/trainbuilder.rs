@@ -3,9 +3,9 @@ use bevy_rapier2d::prelude::{
     ActiveCollisionTypes, ActiveEvents, Collider, CollisionGroups, Group, ReadRapierContext,
     Sensor,
 };
-use petgraph::EdgeDirection;
 
 use crate::{
+    commands::SimCommand,
     input::{MenuState, SpawningState},
     interact::{InteractionNode, InteractionStatus, TileClickEvent, TrainClickEvent},
     ok_or_return,
@@ -19,10 +19,15 @@ pub struct TrainBuildingPlugin;
 
 impl Plugin for TrainBuildingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, train_builder.run_if(in_state(MenuState::Spawning)))
-            .add_observer(append_vehicle_system.run_if(in_state(MenuState::Spawning)))
-            .add_observer(uncoupling_system)
-            .add_observer(coupling_system);
+        app.add_systems(
+            Update,
+            (train_builder, despawn_train_system).run_if(in_state(MenuState::Spawning)),
+        )
+        .add_observer(append_vehicle_system.run_if(in_state(MenuState::Spawning)))
+        .add_observer(despawn_vehicle_system.run_if(in_state(MenuState::Spawning)))
+        .add_observer(rerail_system.run_if(in_state(MenuState::Spawning)))
+        .add_observer(uncoupling_system.run_if(not(vehicle_tool_active)))
+        .add_observer(coupling_system.run_if(not(vehicle_tool_active)));
     }
 }
 
@@ -71,6 +76,40 @@ impl Trail {
         }
     }
 
+    /// Fits this trail onto the current rail network, by cutting off the parts in front
+    /// of and behind the active segment which aren't tracks anymore.
+    ///
+    /// Returns false and leaves the trail untouched, if the active segment itself
+    /// isn't completely on the network.
+    fn snap_to_graph(&mut self, rail_graph: &RailGraph) -> bool {
+        let is_track = |edge: &[Joint]| rail_graph.graph.contains_edge(edge[0], edge[1]);
+        if !self.trim().windows(2).all(is_track) {
+            return false;
+        }
+
+        let lead_start = self.path_progress.ceil() as usize;
+        let lead_cut = self
+            .path
+            .windows(2)
+            .enumerate()
+            .skip(lead_start)
+            .find(|(_, edge)| !is_track(edge))
+            .map(|(index, _)| index);
+        if let Some(index) = lead_cut {
+            self.path.truncate(index + 1);
+        }
+
+        let tail_end = self.back_index();
+        let tail_cut = self.path[..=tail_end]
+            .windows(2)
+            .rposition(|edge| !is_track(edge));
+        if let Some(index) = tail_cut {
+            self.path.drain(..=index);
+            self.path_progress -= (index + 1) as f32;
+        }
+        true
+    }
+
     /// Appends `back` to `front`.
     ///
     /// If they do not overlap, this returns `Err`.
@@ -112,13 +151,11 @@ impl Trail {
 
 /// This system tries to place a new train on click
 fn train_builder(
-    mut commands: Commands,
-    atlas: Res<SpriteAssets>,
     mut click_event: MessageReader<TileClickEvent>,
+    mut sim_commands: MessageWriter<SimCommand>,
     rail_graph: Res<RailGraph>,
     state: Res<State<SpawningState>>,
 ) {
-    let graph = rail_graph.as_ref();
     let SpawningState::SpawnVehicle(wagon_type) = *state.get() else {
         // Events are irrelevant
         click_event.clear();
@@ -134,19 +171,74 @@ fn train_builder(
             tile: ev.coord,
             side,
         };
-        let neighbor_count = graph
-            .graph
-            .neighbors_directed(face, EdgeDirection::Outgoing)
-            .count();
-        if neighbor_count == 0 {
+        if rail_graph.route_from(face).is_none() {
             // skip if there is no rail in the graph at this position
             continue;
         }
 
-        create_new_train(&mut commands, &atlas, face, &graph, wagon_type);
+        sim_commands.write(SimCommand::SpawnTrain {
+            joint: face,
+            vehicle_type: wagon_type,
+        });
     }
 }
 
+/// Creates a new train with a single vehicle of `vehicle_type` at `face`,
+/// if there is a track there.
+pub(crate) fn spawn_train(
+    In((face, vehicle_type)): In<(Joint, VehicleType)>,
+    mut commands: Commands,
+    assets: Option<Res<SpriteAssets>>,
+    rail_graph: Res<RailGraph>,
+) {
+    if rail_graph.route_from(face).is_none() {
+        warn!("Cannot spawn a train @{:?}, there is no track", face.tile);
+        return;
+    }
+    let default_assets = SpriteAssets::default();
+    let assets = assets.as_deref().unwrap_or(&default_assets);
+    create_new_train(&mut commands, assets, face, &rail_graph, vehicle_type);
+}
+
+/// This system despawns whole trains standing on the clicked tile.
+fn despawn_train_system(
+    mut click_event: MessageReader<TileClickEvent>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    trains: Query<(&TrainId, &Trail), With<TrainMarker>>,
+    state: Res<State<SpawningState>>,
+) {
+    if *state.get() != SpawningState::Despawn {
+        // Events are irrelevant
+        click_event.clear();
+        return;
+    }
+
+    for ev in click_event.read() {
+        for (&train, trail) in &trains {
+            // The tile of the first joint of a track is the one it goes through.
+            if trail.trim().windows(2).any(|edge| edge[0].tile == ev.coord) {
+                sim_commands.write(SimCommand::DespawnTrain(train));
+            }
+        }
+    }
+}
+
+/// Run condition for whether clicks on trains are used by the despawn or rerail tools,
+/// in which case they shouldn't couple or uncouple the train aswell.
+fn vehicle_tool_active(
+    menu_state: Option<Res<State<MenuState>>>,
+    spawning_state: Option<Res<State<SpawningState>>>,
+) -> bool {
+    let (Some(menu_state), Some(spawning_state)) = (menu_state, spawning_state) else {
+        return false;
+    };
+    *menu_state.get() == MenuState::Spawning
+        && matches!(
+            spawning_state.get(),
+            SpawningState::Despawn | SpawningState::Rerail
+        )
+}
+
 /// Temporary dev testing system to add vehicles to the end of trains.
 ///
 /// Removed support for inserting at beginning or middle since it isn't planned feature.
@@ -156,9 +248,8 @@ fn train_builder(
 fn append_vehicle_system(
     trigger: On<TrainClickEvent>,
     state: Res<State<SpawningState>>,
-    trains: Query<&Trail>,
-    mut commands: Commands,
-    atlas: Res<SpriteAssets>,
+    trains: Query<(&Trail, &TrainId)>,
+    mut sim_commands: MessageWriter<SimCommand>,
 ) {
     let SpawningState::SpawnVehicle(wagon_type) = state.get() else {
         // Event is irrelevant
@@ -166,8 +257,7 @@ fn append_vehicle_system(
     };
 
     let ev = trigger.event();
-    let train_id = ev.train;
-    let Ok(trail) = trains.get(train_id) else {
+    let Ok((trail, &train)) = trains.get(ev.train) else {
         warn!("Mismatch train query");
         return;
     };
@@ -175,19 +265,37 @@ fn append_vehicle_system(
         // Can only append at the end
         return;
     }
-    if trail.length as f32 + 1. > trail.path_progress {
+    sim_commands.write(SimCommand::AppendVehicle {
+        train,
+        vehicle_type: *wagon_type,
+    });
+}
+
+/// Appends a vehicle of `wagon_type` to the end of the train, if its trail is long enough.
+pub(crate) fn append_vehicle(
+    In((train_id, wagon_type)): In<(Entity, VehicleType)>,
+    trains: Query<&Trail>,
+    mut commands: Commands,
+    assets: Option<Res<SpriteAssets>>,
+) {
+    let Ok(trail) = trains.get(train_id) else {
+        warn!("Mismatch train query");
+        return;
+    };
+    if trail.length as f32 + 1. > trail.distance_from_start() {
         warn!("Cannot append vehicle to train {train_id:?} since the trail is too short");
         return;
     }
 
     debug!("Appending a vehicle to train {train_id:?}");
 
+    let default_assets = SpriteAssets::default();
     let new_wagon = spawn_wagon(
         &mut commands,
-        &atlas,
-        *wagon_type,
-        VehicleStats::default_for_type(*wagon_type),
-        ev.bumper_index,
+        assets.as_deref().unwrap_or(&default_assets),
+        wagon_type,
+        VehicleStats::default_for_type(wagon_type),
+        trail.length,
     );
     commands.entity(new_wagon).insert(VehicleOf(train_id));
     commands.queue(move |world: &mut World| {
@@ -204,91 +312,233 @@ fn append_vehicle_system(
     });
 }
 
-fn coupling_system(
+/// System to remove the clicked vehicle from its train.
+fn despawn_vehicle_system(
     trigger: On<TrainClickEvent>,
+    state: Res<State<SpawningState>>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    bumpers: Query<&ChildOf, With<BumperNode>>,
+    vehicles: Query<&TrainIndex>,
+    trains: Query<&TrainId>,
+) {
+    if *state.get() != SpawningState::Despawn {
+        // Event is irrelevant
+        return;
+    }
+
+    let ev = trigger.event();
+    let vehicle_id = ok_or_return!(
+        bumpers.get(ev.bumper_entity),
+        "BumperNode should always be attached to a vehicle"
+    )
+    .parent();
+    let index = ok_or_return!(vehicles.get(vehicle_id), "clicked vehicle is malformed");
+    let &train = ok_or_return!(trains.get(ev.train), "clicked train is malformed");
+    sim_commands.write(SimCommand::RemoveVehicle {
+        train,
+        index: index.position,
+    });
+}
+
+/// Removes the vehicle at `index` from the train.
+///
+/// The vehicles behind it are split off into a new train, just like [`uncouple`] does,
+/// and the train is despawned if it doesn't have any vehicles left.
+pub(crate) fn remove_vehicle(
+    In((train, index)): In<(Entity, u16)>,
     mut commands: Commands,
-    rapier_context: ReadRapierContext,
-    trains: Query<&Trail, With<TrainMarker>>,
-    vehicles: Query<&VehicleOf, With<VehicleType>>,
-    bumpers: Query<(&ChildOf, &BumperNode)>,
+    vehicles: Query<(Entity, &TrainIndex)>,
+    trains: Query<(&Trail, &Vehicles), With<TrainMarker>>,
 ) {
-    fn couple_trains(
-        In((t1, d1, t2, d2)): In<(Entity, BumperNode, Entity, BumperNode)>,
-        mut commands: Commands,
-        trains: Query<&Trail, With<TrainMarker>>,
-        child_query: Query<&Vehicles>,
-    ) {
-        let trail1 = ok_or_return!(trains.get(t1), "not a train");
-        let trail2 = ok_or_return!(trains.get(t2), "not a train");
+    let Ok((trail, train_vehicles)) = trains.get(train) else {
+        warn!("Cannot remove a vehicle of {train:?}, it isn't a train (anymore)");
+        return;
+    };
+    let Some((vehicle_id, _)) = train_vehicles
+        .iter()
+        .filter_map(|e| vehicles.get(e).ok())
+        .find(|(_, idx)| idx.position == index)
+    else {
+        warn!("Train {train:?} has no vehicle at index {index}");
+        return;
+    };
 
-        // Cases:
-        // All cases are combined in 3 steps: figure out which one is the front train,
-        // the one which will survive and has the back appened to it.
-        // Then reverse one of the trains, given by `reverse`, and add the value from
-        // front length to all indices of the back train.
-        let ((front_id, front), (back_id, back), reverse) = match (d1, d2) {
-            // Front / back -> reindex back
-            (BumperNode::Front, BumperNode::Back) => ((t2, trail2), (t1, trail1), None),
-            (BumperNode::Back, BumperNode::Front) => ((t1, trail1), (t2, trail2), None),
-            // Front / front -> reverse one, reindex other
-            (BumperNode::Front, BumperNode::Front) => ((t1, trail1), (t2, trail2), Some(t1)),
-            // Back / back -> reverse & reindex one
-            (BumperNode::Back, BumperNode::Back) => ((t1, trail1), (t2, trail2), Some(t2)),
+    let front_length = index;
+    let back_length = trail.length - index - 1;
+    debug!("Despawning vehicle {index} of train {train:?}");
+
+    if back_length > 0 {
+        // This is unsorted tho
+        let to_reparent = train_vehicles
+            .iter()
+            .filter_map(|e| vehicles.get(e).ok())
+            .filter(|(_, idx)| idx.position > front_length)
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+
+        let mut back_trail = Trail {
+            path: trail.path.clone(),
+            // The front of the new train is at the back of the removed vehicle
+            path_progress: trail.progress_behind((front_length + 1) as f32),
+            length: back_length,
         };
-        let front_len = front.length;
-
-        // Construct a new trail anyway, with maybe too much cloning
-        // TODO: Check the gap here aswell
-        // let gap = some_or_return!(trail2.gap_to(trail1));
-        let Ok(new_trail) = (match reverse {
-            None => Trail::clone_from_parts(front, back),
-            Some(reverse) if reverse == front_id => {
-                let mut front = front.clone();
-                front.reverse();
-                Trail::clone_from_parts(&front, back)
+        back_trail.remove_lead();
+
+        let new_train_id = commands
+            .spawn(TrainBundle::new(
+                back_trail, 55.0, // approx 200kmh
+            ))
+            .add_related::<VehicleOf>(&to_reparent)
+            .id();
+        let diff = -(front_length as i16 + 1);
+        commands.queue(move |world: &mut World| {
+            if let Err(e) = world.run_system_once_with(reindex_train, (new_train_id, diff)) {
+                error!("reindex_train failed: {e:?}");
             }
-            Some(reverse) if reverse == back_id => {
-                let mut back = back.clone();
-                back.reverse();
-                Trail::clone_from_parts(front, &back)
+        });
+    }
+
+    // Also despawns the bumpers.
+    commands.entity(vehicle_id).despawn();
+
+    if front_length == 0 {
+        // The remaining vehicles were already moved to the new train.
+        commands.entity(train).despawn();
+    } else {
+        commands.queue(move |world: &mut World| {
+            if let Err(e) = world.run_system_once_with(set_train_length, (train, front_length)) {
+                error!("set_train_length failed: {e:?}");
             }
-            Some(_) => unreachable!("Was set to one of these values above"),
-        }) else {
-            warn!("Trails do not align");
-            return;
-        };
+        });
+    }
+}
 
-        debug!("Coupling train {back_id:?} to {front_id:?}");
+/// System to put a (crashed) train back onto the rails on click.
+fn rerail_system(
+    trigger: On<TrainClickEvent>,
+    state: Res<State<SpawningState>>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    trains: Query<&TrainId>,
+) {
+    if *state.get() != SpawningState::Rerail {
+        // Event is irrelevant
+        return;
+    }
 
-        // Queue all the commands. I rely on them being executed in order.
-        if let Some(reverse) = reverse {
-            // Reverse train indices of one of the trains
-            commands.queue(move |world: &mut World| {
-                if let Err(e) = world.run_system_once_with(reverse_train, reverse) {
-                    error!("reverse_train failed: {e:?}");
-                }
-            });
+    let &train = ok_or_return!(
+        trains.get(trigger.event().train),
+        "clicked train is malformed"
+    );
+    sim_commands.write(SimCommand::Rerail(train));
+}
+
+/// Stops the train and fits its trail to the current rail network,
+/// but only if the train is still standing on tracks.
+pub(crate) fn rerail_train(
+    In(train): In<Entity>,
+    mut commands: Commands,
+    rail_graph: Res<RailGraph>,
+    mut trains: Query<(&mut Trail, &mut Velocity, &mut Controller), With<TrainMarker>>,
+) {
+    let (mut trail, mut velocity, mut controller) =
+        ok_or_return!(trains.get_mut(train), "rerailed train is malformed");
+
+    if !trail.snap_to_graph(&rail_graph) {
+        warn!("Cannot rerail train {train:?}, since it isn't standing on tracks");
+        return;
+    }
+    velocity.velocity = 0.0;
+    *controller = Controller::default();
+    commands.entity(train).try_remove::<Crashed>();
+
+    debug!("Rerailed train {train:?}");
+}
+
+/// Couples the train `t1` at its end `d1` to the end `d2` of train `t2`.
+pub(crate) fn couple_trains(
+    In((t1, d1, t2, d2)): In<(Entity, BumperNode, Entity, BumperNode)>,
+    mut commands: Commands,
+    trains: Query<&Trail, With<TrainMarker>>,
+    child_query: Query<&Vehicles>,
+) {
+    let trail1 = ok_or_return!(trains.get(t1), "not a train");
+    let trail2 = ok_or_return!(trains.get(t2), "not a train");
+
+    // Cases:
+    // All cases are combined in 3 steps: figure out which one is the front train,
+    // the one which will survive and has the back appened to it.
+    // Then reverse one of the trains, given by `reverse`, and add the value from
+    // front length to all indices of the back train.
+    let ((front_id, front), (back_id, back), reverse) = match (d1, d2) {
+        // Front / back -> reindex back
+        (BumperNode::Front, BumperNode::Back) => ((t2, trail2), (t1, trail1), None),
+        (BumperNode::Back, BumperNode::Front) => ((t1, trail1), (t2, trail2), None),
+        // Front / front -> reverse one, reindex other
+        (BumperNode::Front, BumperNode::Front) => ((t1, trail1), (t2, trail2), Some(t1)),
+        // Back / back -> reverse & reindex one
+        (BumperNode::Back, BumperNode::Back) => ((t1, trail1), (t2, trail2), Some(t2)),
+    };
+    let front_len = front.length;
+
+    // Construct a new trail anyway, with maybe too much cloning
+    // TODO: Check the gap here aswell
+    // let gap = some_or_return!(trail2.gap_to(trail1));
+    let Ok(new_trail) = (match reverse {
+        None => Trail::clone_from_parts(front, back),
+        Some(reverse) if reverse == front_id => {
+            let mut front = front.clone();
+            front.reverse();
+            Trail::clone_from_parts(&front, back)
+        }
+        Some(reverse) if reverse == back_id => {
+            let mut back = back.clone();
+            back.reverse();
+            Trail::clone_from_parts(front, &back)
         }
+        Some(_) => unreachable!("Was set to one of these values above"),
+    }) else {
+        warn!("Trails do not align");
+        return;
+    };
+
+    debug!("Coupling train {back_id:?} to {front_id:?}");
+
+    // Queue all the commands. I rely on them being executed in order.
+    if let Some(reverse) = reverse {
+        // Reverse train indices of one of the trains
         commands.queue(move |world: &mut World| {
-            if let Err(e) =
-                world.run_system_once_with(reindex_train, (back_id, front_len as i16))
-            {
-                error!("reindex_train failed: {e:?}");
+            if let Err(e) = world.run_system_once_with(reverse_train, reverse) {
+                error!("reverse_train failed: {e:?}");
             }
         });
-        commands
-            .entity(front_id)
-            // Overrite old trail component
-            .insert(new_trail)
-            // And append all newly gained vehicles
-            .add_related::<VehicleOf>(ok_or_return!(
-                child_query.get(back_id),
-                "back has no vehicles?"
-            ));
-        // No recursive needed, vehicles have just been moved
-        commands.entity(back_id).despawn();
     }
+    commands.queue(move |world: &mut World| {
+        if let Err(e) = world.run_system_once_with(reindex_train, (back_id, front_len as i16)) {
+            error!("reindex_train failed: {e:?}");
+        }
+    });
+    commands
+        .entity(front_id)
+        // Overrite old trail component
+        .insert(new_trail)
+        // And append all newly gained vehicles
+        .add_related::<VehicleOf>(ok_or_return!(
+            child_query.get(back_id),
+            "back has no vehicles?"
+        ));
+    // No recursive needed, vehicles have just been moved
+    commands.entity(back_id).despawn();
+}
 
+fn coupling_system(
+    trigger: On<TrainClickEvent>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    rapier_context: ReadRapierContext,
+    trains: Query<&Trail, With<TrainMarker>>,
+    train_ids: Query<&TrainId>,
+    vehicles: Query<&VehicleOf, With<VehicleType>>,
+    bumpers: Query<(&ChildOf, &BumperNode)>,
+) {
     let ev = trigger.event();
     let Ok(trail) = trains.get(ev.train) else {
         error!("Train not found");
@@ -331,30 +581,52 @@ fn coupling_system(
         return;
     }
 
-    commands.queue(move |world: &mut World| {
-        // This actually leads to a two deep `run_system_once_with` invocation,
-        // I hope this is fine.
-        // TODO: for better performance, use a registered system.
-        if let Err(e) = world.run_system_once_with(couple_trains, (t1, d1, t2, d2)) {
-            error!("couple_trains failed: {e:?}");
-        }
-    })
+    let (Ok(&first), Ok(&second)) = (train_ids.get(t1), train_ids.get(t2)) else {
+        error!("Train without id!");
+        return;
+    };
+    sim_commands.write(SimCommand::Couple {
+        first,
+        first_end: d1,
+        second,
+        second_end: d2,
+    });
 }
 
 fn uncoupling_system(
     trigger: On<TrainClickEvent>,
-    mut commands: Commands,
-    vehicles: Query<(Entity, &TrainIndex)>,
-    trains: Query<(Entity, &Trail, &Vehicles)>,
+    mut sim_commands: MessageWriter<SimCommand>,
+    trains: Query<(&Trail, &TrainId)>,
 ) {
     let ev = trigger.event();
-    let Ok((train, trail, train_vehicles)) = trains.get(ev.train) else {
+    let Ok((trail, &train)) = trains.get(ev.train) else {
         warn!("Train click event for not-query-matching entity (despawned or malformed)");
         return;
     };
+    if ev.bumper_index == 0 || ev.bumper_index >= trail.length {
+        // Already at an end of the train
+        return;
+    }
+    sim_commands.write(SimCommand::Uncouple {
+        train,
+        index: ev.bumper_index,
+    });
+}
+
+/// Splits the vehicles from `index` on off the train into a new train.
+pub(crate) fn uncouple(
+    In((train, index)): In<(Entity, u16)>,
+    mut commands: Commands,
+    vehicles: Query<(Entity, &TrainIndex)>,
+    trains: Query<(&Trail, &Vehicles)>,
+) {
+    let Ok((trail, train_vehicles)) = trains.get(train) else {
+        warn!("Cannot uncouple {train:?}, it isn't a train (anymore)");
+        return;
+    };
 
-    let front_length = ev.bumper_index;
-    let back_length = trail.length - ev.bumper_index;
+    let front_length = index;
+    let back_length = trail.length.saturating_sub(index);
 
     if front_length == 0 || back_length == 0 {
         // Already at an end of the train
@@ -374,14 +646,14 @@ fn uncoupling_system(
     let to_reparent = train_vehicles
         .iter()
         .filter_map(|e| vehicles.get(e).ok())
-        .filter(|(_, idx)| idx.position >= ev.bumper_index)
+        .filter(|(_, idx)| idx.position >= index)
         .map(|(e, _)| e)
         .collect::<Vec<_>>();
 
     let mut back_trail = Trail {
         // This shouldn't break any trail invariants...
         path: trail.path.clone(),
-        path_progress: trail.path_progress - front_length as f32,
+        path_progress: trail.progress_behind(front_length as f32),
         length: back_length,
     };
     back_trail.remove_lead();
@@ -406,7 +678,7 @@ fn uncoupling_system(
     })
 }
 
-/// A mutating part of the `uncoupling_system`, since I want to apply them
+/// A mutating part of [`uncouple`], since I want to apply them
 /// at a controlled time.
 fn set_train_length(
     In((train_id, new_len)): In<(Entity, u16)>,
@@ -452,10 +724,9 @@ fn create_new_train(
     info!("Creating train at @{:?}", face.tile);
 
     let next_face = rail_graph
-        .graph
-        .neighbors_directed(face, EdgeDirection::Outgoing)
-        .next()
-        .expect("Broke precondition: `face is in the graph and has a neighbor`!");
+        .route_from(face)
+        .expect("Broke precondition: `face is in the graph and has a neighbor`!")
+        .end_joint();
 
     let first_wagon = spawn_wagon(
         commands,
@@ -1,29 +1,74 @@
 use std::{f32::consts::PI, ops::Add};
 
-use bevy::prelude::*;
+use bevy::{ecs::lifecycle::HookContext, ecs::world::DeferredWorld, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use crate::commands::SimCommand;
+use crate::input::{MenuAction, MenuInput};
 use crate::ok_or_return;
+use crate::orders::Orders;
+use crate::railroad::{RailGraph, Track};
 use crate::sprites::BaseSpriteBundle;
 use crate::tilemap::{Joint, Tile};
 
 /// The length in meters that a single track covers.
 ///
 /// I.e. the width of the hexagons and length of the vehicles in meters.
-const METER_PER_TRACK: f32 = 10.;
+pub const METER_PER_TRACK: f32 = 10.;
 
+/// Moves trains along their trails, part of the [`SimulationPlugins`](crate::SimulationPlugins).
 pub struct TrainPlugin;
 impl Plugin for TrainPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Time::<Fixed>::from_seconds(1. / 64.))
+            .init_resource::<SpeedLimitMode>()
+            .init_resource::<NextTrainId>()
+            .configure_sets(
+                FixedUpdate,
+                (ControlSet::Drive, ControlSet::Protect)
+                    .chain()
+                    .before(tick_velocity),
+            )
             .add_systems(
                 FixedUpdate,
                 (tick_velocity.before(tick_trains), tick_trains),
             )
-            .add_systems(PostUpdate, (position_train_units, update_tint));
+            // In the fixed schedule aswell, before the physics step in `FixedPostUpdate`,
+            // so the colliders only ever see whole steps.
+            .add_systems(FixedUpdate, position_train_units.after(tick_trains));
     }
 }
 
+/// Tints crashed trains and lets the player toggle the [`SpeedLimitMode`].
+pub struct TrainUiPlugin;
+impl Plugin for TrainUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, toggle_speed_limit_mode)
+            .add_systems(PostUpdate, update_tint);
+    }
+}
+
+/// The systems in `FixedUpdate` which set the [`Controller`] of trains,
+/// before the velocity is updated from it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ControlSet {
+    /// Systems driving a train, e.g. an autopilot.
+    Drive,
+    /// Systems overriding the driver to keep the train safe, e.g. signals,
+    /// by setting its [`Protection`].
+    Protect,
+}
+
+/// What happens to trains driving faster than the speed limit of a track they are on.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedLimitMode {
+    /// The velocity is clamped to the speed limit.
+    #[default]
+    Clamp,
+    /// The train derails, i.e. it is [`Crashed`].
+    Derail,
+}
+
 // ================================ TRAINS ===================================
 
 /// The components of an entity that make up a logical train.
@@ -39,15 +84,65 @@ pub struct TrainBundle {
     pub path: Trail,
     pub velocity: Velocity,
     pub controller: Controller,
+    pub protection: Protection,
+    pub orders: Orders,
+    pub id: TrainId,
 
-    /// Currently always "Train" for inspection
+    /// "Train" by default, kept in savegames.
     pub name: Name,
 }
 
+/// A number identifying a train, unlike its [`Entity`] it is kept in savegames,
+/// so [`SimCommand`]s can refer to trains in a replay.
+///
+/// Trains spawned with [`TrainId::UNASSIGNED`] get the [`NextTrainId`] when inserted.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[component(on_insert = assign_train_id)]
+pub struct TrainId(pub u32);
+
+impl TrainId {
+    pub const UNASSIGNED: TrainId = TrainId(0);
+}
+
+impl Default for TrainId {
+    fn default() -> Self {
+        Self::UNASSIGNED
+    }
+}
+
+/// The id the next spawned train gets, always greater than the ids of all trains.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NextTrainId(pub u32);
+
+impl Default for NextTrainId {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+fn assign_train_id(mut world: DeferredWorld, context: HookContext) {
+    let Some(id) = world.get::<TrainId>(context.entity).copied() else {
+        return;
+    };
+    let Some(mut next_id) = world.get_resource_mut::<NextTrainId>() else {
+        // Outside of the simulation, e.g. while building a world for a test
+        return;
+    };
+    if id == TrainId::UNASSIGNED {
+        let assigned = TrainId(next_id.0);
+        next_id.0 += 1;
+        if let Some(mut train_id) = world.get_mut::<TrainId>(context.entity) {
+            *train_id = assigned;
+        }
+    } else {
+        next_id.0 = next_id.0.max(id.0 + 1);
+    }
+}
+
 /// Which train a vehicle (wagon or locomotive) logically belongs to.
 ///
 /// This is deliberately *not* [`ChildOf`], since a vehicle's [`Transform`] is written directly
-/// in world space every frame by [`position_train_units`] rather than being relative to the
+/// in world space every fixed step by [`position_train_units`] rather than being relative to the
 /// train, so real spatial parenting (and its `GlobalTransform` propagation) would be wasted
 /// work and a footgun if the train entity ever got a non-identity transform.
 #[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
@@ -78,10 +173,11 @@ pub struct TrainMarker;
 
 #[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Trail {
-    /// From 0 at the start to len at the destination. Must contain at least `length + 1` values.
+    /// From 0 at the start to len at the destination. Must be long enough to fit the whole
+    /// train behind the front, see [`Trail::check_invariant`].
     pub path: Vec<Joint>,
     /// A fractional index into path, where the front of the train is.
-    /// Must obey `length <= path_progress <= path.len() - 1` (todo: check)
+    /// Must obey `path_progress <= path.len() - 1`, with `length` tracks behind it.
     pub path_progress: f32,
     /// Amount of wagons and locomotives. Must be equal to the number of [`TrainIndex`] children.
     pub length: u16,
@@ -97,7 +193,7 @@ pub struct Velocity {
     // Stats that affect the moving object are given by the sum of all vehicles.
 }
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Controller {
     /// The fraction of the power being applied. Must be in the interval [0, 1].
     pub throttle: f32,
@@ -105,6 +201,28 @@ pub struct Controller {
     pub brake: f32,
 }
 
+/// Overrides the [`Controller`] of a train for a single tick, without changing the pedals
+/// of its driver. Set by the systems in [`ControlSet::Protect`] and reset once it is applied.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq)]
+pub struct Protection {
+    /// The least fraction of the brake being applied. While it is positive, there is no power.
+    pub brake: f32,
+}
+
+impl Protection {
+    /// Returns the throttle and brake actually applied to a train driven with `controller`.
+    pub fn apply(&self, controller: Controller) -> Controller {
+        if self.brake > 0.0 {
+            Controller {
+                throttle: 0.0,
+                brake: controller.brake.max(self.brake),
+            }
+        } else {
+            controller
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct PlayerControlledTrain;
 
@@ -159,7 +277,7 @@ pub struct TrainIndex {
 // ============================= Vehicle parts ================================
 
 /// The component attached to the collider children of vehicles.
-#[derive(Component, Debug, Clone, Copy)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BumperNode {
     Front,
     Back,
@@ -183,6 +301,12 @@ impl VehicleStats {
         }
     }
 
+    /// Returns the distance in meters needed to stop from `velocity` with full brakes.
+    pub fn braking_distance(&self, velocity: f32) -> f32 {
+        let decceleration = self.braking_force / self.weight;
+        velocity * velocity / (2. * decceleration)
+    }
+
     pub fn additive_identiy() -> Self {
         VehicleStats {
             weight: 0.,
@@ -204,27 +328,126 @@ impl Add<&VehicleStats> for VehicleStats {
     }
 }
 
+/// Returns the length of the track between two consecutive joints of a path,
+/// see [`Track::length`].
+pub(crate) fn track_length(start: Joint, end: Joint) -> f32 {
+    Track::from_joints(start, end).map_or(1.0, |track| track.length())
+}
+
 impl Trail {
-    /// Returns the point on the trail for a given fractional index
-    /// as a pair of start, end joint plus an interpolation value.
+    /// Returns the point on the trail for a given index as a pair of start, end joint plus
+    /// an interpolation value.
     ///
-    /// `index = 0` corresponds to the front bumper of the train,
-    /// `index = length` to the back bumper.
+    /// `index` is the distance from the front bumper of the train along the trail,
+    /// measured in straight tracks. I.e. `index = 0` corresponds to the front bumper of
+    /// the train and `index = length` to the back bumper, which can be more than `length`
+    /// tracks behind the front on curves.
     /// Interpolation value 0 means the first joint in the tuple, 1 the second.
     /// The first joint is always the one towards the back of the trail.
     ///
-    /// Returns `None` if the index does not have joints anymore in this trail.
-    /// This can be outside of the active segment of this trail.
-    /// Should never return `None` if the trail is upholds invariants and
-    /// `index` is in range [0, length).
+    /// This can be outside of the active segment of this trail. If the path doesn't reach
+    /// back far enough, the start of the path is returned.
+    /// Returns `Err` if the trail doesn't uphold its invariants.
     pub fn point_on_trail(&self, index: f32) -> Result<(Joint, Joint, f32), ()> {
         if !self.check_invariant() {
             return Err(());
         }
-        let progress = self.path_progress - index;
-        let &start = self.path.get(progress.floor() as usize).ok_or(())?;
-        let &end = self.path.get(progress.floor() as usize + 1).ok_or(())?;
-        Ok((start, end, progress.fract()))
+        let (segment, fraction) = self.walk_back(index).ok_or(())?;
+        Ok((self.path[segment], self.path[segment + 1], fraction))
+    }
+
+    /// Returns the index of the track `distance` behind the front and the fraction of it
+    /// covered there, see [`Trail::point_on_trail`].
+    fn walk_back(&self, distance: f32) -> Option<(usize, f32)> {
+        let mut segment = self.path_progress.floor() as usize;
+        let mut fraction = self.path_progress.fract();
+        if fraction == 0.0 && segment > 0 {
+            // The front is exactly at a joint, which is the end of the previous track
+            segment -= 1;
+            fraction = 1.0;
+        }
+
+        // Tracks differ in length
+        let mut remaining = distance;
+        loop {
+            let &start = self.path.get(segment)?;
+            let &end = self.path.get(segment + 1)?;
+            let length = track_length(start, end);
+            if remaining <= fraction * length {
+                return Some((segment, fraction - remaining / length));
+            }
+            if segment == 0 {
+                return Some((0, 0.0));
+            }
+            remaining -= fraction * length;
+            segment -= 1;
+            fraction = 1.0;
+        }
+    }
+
+    /// Returns the fractional index into the path of the point `distance` behind the front,
+    /// measured in straight tracks, see [`Trail::point_on_trail`].
+    pub fn progress_behind(&self, distance: f32) -> f32 {
+        self.walk_back(distance)
+            .map_or(0.0, |(segment, fraction)| segment as f32 + fraction)
+    }
+
+    /// Returns the distance from the start of the path to the front, measured in straight
+    /// tracks.
+    pub fn distance_from_start(&self) -> f32 {
+        let segment = self.path_progress.floor() as usize;
+        self.path
+            .windows(2)
+            .take(segment + 1)
+            .enumerate()
+            .map(|(index, edge)| {
+                let covered = if index == segment {
+                    self.path_progress.fract()
+                } else {
+                    1.0
+                };
+                covered * track_length(edge[0], edge[1])
+            })
+            .sum()
+    }
+
+    /// Moves the front of the trail forward by `distance`, measured in straight tracks.
+    ///
+    /// Stops at the end of the path.
+    pub fn advance(&mut self, mut distance: f32) {
+        while distance > 0.0 {
+            let segment = self.path_progress.floor() as usize;
+            let (Some(&start), Some(&end)) = (self.path.get(segment), self.path.get(segment + 1))
+            else {
+                break;
+            };
+            let length = track_length(start, end);
+            let left_in_track = (1.0 - self.path_progress.fract()) * length;
+            if distance < left_in_track {
+                self.path_progress += distance / length;
+                break;
+            }
+            distance -= left_in_track;
+            self.path_progress = (segment + 1) as f32;
+        }
+    }
+
+    /// Returns the distance from the front to the end of the path, measured in straight tracks.
+    pub fn distance_to_end(&self) -> f32 {
+        let segment = self.path_progress.floor() as usize;
+        self.path
+            .windows(2)
+            .enumerate()
+            .skip(segment)
+            .map(|(index, edge)| {
+                let covered = if index == segment {
+                    self.path_progress.fract()
+                } else {
+                    0.0
+                };
+                (1.0 - covered) * track_length(edge[0], edge[1])
+            })
+            .sum()
     }
 
     pub fn trim_front(&self) -> &[Joint] {
@@ -232,23 +455,30 @@ impl Trail {
     }
 
     pub fn trim_back(&self) -> &[Joint] {
-        // FIXME: is this correct?
-        &self.path[(self.path_progress.floor() as usize - self.length as usize)..]
+        &self.path[self.back_index()..]
     }
 
     pub fn trim(&self) -> &[Joint] {
-        &self.path[(self.path_progress.floor() as usize - self.length as usize)
-            ..=(self.path_progress.ceil() as usize)]
+        &self.path[self.back_index()..=(self.path_progress.ceil() as usize)]
+    }
+
+    /// The index of the joint at the start of the track the back bumper is on.
+    pub fn back_index(&self) -> usize {
+        let by_count = (self.path_progress.floor() as usize).saturating_sub(self.length as usize);
+        // On curves, the train covers more than `length` tracks
+        self.walk_back(self.length as f32)
+            .map_or(by_count, |(segment, _)| segment.min(by_count))
     }
 
-    /// Reverses the direction of this trail.
+    /// Reverses the direction of this trail, the back bumper becomes the front.
     pub fn reverse(&mut self) {
+        let back = self.progress_behind(self.length as f32);
         // Reverse the path, use reversed edges and update the progress
         self.path.reverse();
         for d in self.path.iter_mut() {
             *d = d.opposite();
         }
-        self.path_progress = self.path.len() as f32 - self.path_progress + self.length as f32 - 1.;
+        self.path_progress = (self.path.len() - 1) as f32 - back;
     }
 
     /// Shortens the path to not contain any extra tiles in front
@@ -258,12 +488,44 @@ impl Trail {
         }
     }
 
+    /// True if the active segment of this trail uses `track` in either direction.
+    pub fn covers_track(&self, track: Track) -> bool {
+        let reversed = track.reversed();
+        self.trim().windows(2).any(|edge| {
+            (edge[0], edge[1]) == (track.joint, track.end_joint())
+                || (edge[0], edge[1]) == (reversed.joint, reversed.end_joint())
+        })
+    }
+
+    /// Shortens the path in front of the train such that it doesn't use `track` anymore.
+    ///
+    /// Only the lead is cut, the active segment is never changed.
+    pub fn cut_lead_at_track(&mut self, track: Track) {
+        let reversed = track.reversed();
+        let lead_start = self.path_progress.ceil() as usize;
+        let cut_index = self
+            .path
+            .windows(2)
+            .enumerate()
+            .skip(lead_start)
+            .find(|(_, edge)| {
+                (edge[0], edge[1]) == (track.joint, track.end_joint())
+                    || (edge[0], edge[1]) == (reversed.joint, reversed.end_joint())
+            })
+            .map(|(index, _)| index);
+        if let Some(index) = cut_index {
+            self.path.truncate(index + 1);
+        }
+    }
+
     /// True if all (locally checkable) invariants are okay.
     #[inline]
     pub fn check_invariant(&self) -> bool {
-        (self.path.len() >= self.length as usize + 1)
-            && (self.path_progress >= self.length as f32 - 0.01)
+        !self.path.is_empty()
+            && (self.path_progress >= 0.0)
             && (self.path_progress <= self.path.len() as f32 - 0.99)
+            // On curves, the train covers more than `length` tracks
+            && (self.distance_from_start() >= self.length as f32 - 0.01)
     }
 }
 
@@ -279,11 +541,7 @@ impl std::fmt::Debug for Trail {
         }
 
         f.debug_list()
-            .entries(
-                self.path[..(self.path_progress.floor() as usize - self.length as usize)]
-                    .iter()
-                    .map(map_tile_only),
-            )
+            .entries(self.path[..self.back_index()].iter().map(map_tile_only))
             .entry(&"|")
             .entries(self.trim().iter().map(map_tile_only))
             .entry(&format!("{:.2} >", self.path_progress))
@@ -307,6 +565,9 @@ impl TrainBundle {
                 max_velocity,
             },
             controller: Default::default(),
+            protection: Default::default(),
+            orders: Default::default(),
+            id: TrainId::UNASSIGNED,
             name: Name::new("Train"),
         }
     }
@@ -314,16 +575,32 @@ impl TrainBundle {
 
 // ================================ SYSTEMS ===================================
 
-/// System to apply throttle/brake to the velocity
+/// System to apply throttle/brake, overridden by the [`Protection`], to the velocity and
+/// enforce the speed limits, see [`SpeedLimitMode`].
 fn tick_velocity(
+    mut commands: Commands,
     time: Res<Time<Fixed>>,
+    rail_graph: Res<RailGraph>,
+    mode: Res<SpeedLimitMode>,
     mut train: Query<
-        (&Controller, &mut Velocity, &Vehicles),
+        (
+            Entity,
+            &Controller,
+            &mut Protection,
+            &mut Velocity,
+            &Trail,
+            &Vehicles,
+        ),
         (With<TrainMarker>, Without<Crashed>),
     >,
     vehicles: Query<&VehicleStats>,
 ) {
-    for (controller, mut velocity, train_vehicles) in train.iter_mut() {
+    for (entity, controller, mut protection, mut velocity, trail, train_vehicles) in
+        train.iter_mut()
+    {
+        let controller = protection.apply(*controller);
+        // Only for this tick, the protecting systems decide again in the next one
+        *protection = Protection::default();
         let total_stats = train_vehicles
             .iter()
             .filter_map(|id| vehicles.get(id).ok())
@@ -338,6 +615,39 @@ fn tick_velocity(
 
         let delta_velocity = (acceleration - decceleration) * time.delta_secs();
         velocity.velocity = (velocity.velocity + delta_velocity).clamp(0., velocity.max_velocity);
+
+        // The slowest track any vehicle is on
+        let speed_limit = trail
+            .trim()
+            .windows(2)
+            .filter_map(|edge| rail_graph.speed_limit(edge[0], edge[1]))
+            .fold(f32::INFINITY, f32::min);
+        if velocity.velocity > speed_limit {
+            match *mode {
+                SpeedLimitMode::Clamp => velocity.velocity = speed_limit,
+                SpeedLimitMode::Derail => {
+                    warn!(
+                        "Train {entity:?} derailed at {:.1} m/s, the limit is {speed_limit} m/s",
+                        velocity.velocity
+                    );
+                    velocity.velocity = 0.0;
+                    commands.entity(entity).insert(Crashed);
+                }
+            }
+        }
+    }
+}
+
+fn toggle_speed_limit_mode(
+    input: Single<&MenuInput>,
+    mode: Res<SpeedLimitMode>,
+    mut sim_commands: MessageWriter<SimCommand>,
+) {
+    if input.just_pressed(&MenuAction::ToggleDerailing) {
+        sim_commands.write(SimCommand::SetSpeedLimitMode(match *mode {
+            SpeedLimitMode::Clamp => SpeedLimitMode::Derail,
+            SpeedLimitMode::Derail => SpeedLimitMode::Clamp,
+        }));
     }
 }
 
@@ -347,7 +657,7 @@ fn tick_trains(
     mut trains: Query<(&mut Trail, &Velocity), With<TrainMarker>>,
 ) {
     for (mut train, velocity) in trains.iter_mut() {
-        train.path_progress += velocity.velocity * time.delta_secs() / METER_PER_TRACK;
+        train.advance(velocity.velocity * time.delta_secs() / METER_PER_TRACK);
         // TODO: crash
         train.path_progress = train.path_progress.clamp(0., (train.path.len() - 1) as f32);
     }
@@ -392,18 +702,30 @@ fn update_tint(
 
 // ================================ FUNCTIONS ===================================
 
-/// Helper to change the `output` Transform to intrapolate the start and end position and rotation
+/// Helper to change the `output` Transform to the point `t` along the track from `start` to `end`.
+///
+/// Straight tracks are interpolated linearly, curves follow a circular arc.
 fn move_train_unit(output: &mut Transform, start: Joint, end: Joint, t: f32) {
-    // todo: actually move in an arc and not linear
     let start_pos = start.world_position();
     let end_pos = end.world_position();
-    let pos = start_pos * (1. - t) + end_pos * t;
     let start_angle = start.side.to_angle();
     let end_angle = end.side.to_angle();
     // From https://gist.github.com/shaunlebron/8832585
     let da = (end_angle - start_angle) % (2. * PI);
     let angle_diff = (2. * da) % (2. * PI) - da;
     let angle = start_angle + angle_diff * t;
+
+    let pos = if angle_diff.abs() < 0.01 {
+        start_pos.lerp(end_pos, t)
+    } else {
+        // The chord of an arc is 2 * radius * sin(angle / 2)
+        let radius = start_pos.distance(end_pos) / (2. * (angle_diff / 2.).sin().abs());
+        // Trains travel against the side of the joint, the center is to the left for
+        // counterclockwise curves and to the right otherwise.
+        let heading = Vec2::from_angle(start_angle + PI);
+        let center = start_pos + heading.perp() * radius * angle_diff.signum();
+        center + Vec2::from_angle(angle_diff * t).rotate(start_pos - center)
+    };
     output.translation = pos.extend(output.translation.z);
     output.rotation = Quat::from_rotation_z(angle);
 }
@@ -424,3 +746,97 @@ pub fn reverse_train(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::railroad::CURVE_LENGTH;
+    use crate::tilemap::Direction;
+
+    /// A straight track, two left curves and another straight track.
+    fn curved_path() -> Vec<Joint> {
+        let start = Joint {
+            tile: Tile(0, 0),
+            side: Direction::EAST,
+        };
+        let mut path = vec![start, start.next_straight()];
+        path.push(path[1].next_left());
+        path.push(path[2].next_left());
+        path.push(path[3].next_straight());
+        path
+    }
+
+    fn trail(path_progress: f32, length: u16) -> Trail {
+        Trail {
+            path: curved_path(),
+            path_progress,
+            length,
+        }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn advances_along_curves() {
+        let mut trail = trail(0.5, 0);
+        trail.advance(0.5 + CURVE_LENGTH / 2.);
+        assert_near(trail.path_progress, 1.5);
+        trail.advance(CURVE_LENGTH / 2. + CURVE_LENGTH + 0.25);
+        assert_near(trail.path_progress, 3.25);
+        // Stops at the end of the path
+        trail.advance(10.0);
+        assert_near(trail.path_progress, 4.0);
+        assert_near(trail.distance_to_end(), 0.0);
+    }
+
+    #[test]
+    fn walks_back_along_curves() {
+        let trail = trail(3.5, 0);
+        assert_near(trail.progress_behind(0.0), 3.5);
+        assert_near(trail.progress_behind(0.5 + CURVE_LENGTH / 2.), 2.5);
+        assert_near(trail.progress_behind(0.5 + CURVE_LENGTH), 2.0);
+        assert_near(trail.progress_behind(0.5 + 2. * CURVE_LENGTH + 0.25), 0.75);
+        // The path doesn't reach back further
+        assert_near(trail.progress_behind(10.0), 0.0);
+        assert_near(trail.distance_from_start(), 1.5 + 2. * CURVE_LENGTH);
+    }
+
+    #[test]
+    fn reverses_along_curves() {
+        let original = trail(3.5, 2);
+        let back = original.progress_behind(2.0);
+        assert_near(back, 1.0 + 1.0 - (1.5 - CURVE_LENGTH) / CURVE_LENGTH);
+
+        let mut reversed = original.clone();
+        reversed.reverse();
+        assert!(reversed.check_invariant());
+        // The back is the new front and the other way around
+        assert_near(reversed.path_progress, 4.0 - back);
+        assert_near(reversed.progress_behind(2.0), 4.0 - 3.5);
+        for (joint, reversed_joint) in original.path.iter().zip(reversed.path.iter().rev()) {
+            assert_eq!(joint.opposite(), *reversed_joint);
+        }
+
+        reversed.reverse();
+        assert_eq!(reversed.path, original.path);
+        assert_near(reversed.path_progress, original.path_progress);
+    }
+
+    #[test]
+    fn curves_are_shorter_than_straights() {
+        // Two curves fit less than two vehicles
+        let on_curves = Trail {
+            path: curved_path()[1..4].to_vec(),
+            path_progress: 2.0,
+            length: 2,
+        };
+        assert!(!on_curves.check_invariant());
+        assert!(trail(3.0, 2).check_invariant());
+        assert!(!trail(4.5, 2).check_invariant());
+    }
+}